@@ -0,0 +1,129 @@
+//! Hiding desktop files that are overridden or installed more than once.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use covey_plugin::ListItem;
+use freedesktop_desktop_entry::PathSource;
+
+use crate::{App, Apps, DesktopFile};
+
+/// Where a desktop file was installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    System,
+    User,
+    Flatpak,
+    Snap,
+}
+
+impl Source {
+    pub fn guess_from(path: &Path) -> Self {
+        match PathSource::guess_from(path) {
+            PathSource::Local | PathSource::LocalDesktop => Self::User,
+            PathSource::LocalFlatpak | PathSource::SystemFlatpak => Self::Flatpak,
+            PathSource::SystemSnap => Self::Snap,
+            _ => Self::System,
+        }
+    }
+
+    fn badge(self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::User => "user",
+            Self::Flatpak => "flatpak",
+            Self::Snap => "snap",
+        }
+    }
+}
+
+/// An app to list, along with other installations of the same app.
+pub struct Listed<'a> {
+    /// Desktop file id of the app.
+    pub id: &'a str,
+    pub app: &'a App,
+    /// The app's item, with a source badge in the description.
+    pub item: ListItem,
+    /// Items for the other installations, shown after completing the app
+    /// name.
+    pub alternatives: Vec<ListItem>,
+}
+
+/// Picks which apps to list.
+///
+/// Only the first desktop file with each id is used, following the order of
+/// the XDG data dirs. Apps with the same name from different sources (like a
+/// system package and a Flatpak) are then merged, keeping the one from the
+/// first data dir and listing the others as alternatives.
+pub fn dedupe(apps: &Apps) -> Vec<Listed<'_>> {
+    // this includes files that are filtered out, so that hiding an app
+    // with a user override works.
+    let mut by_id: HashMap<&str, &DesktopFile> = HashMap::new();
+    for file in apps.values() {
+        by_id
+            .entry(&file.id)
+            .and_modify(|first| {
+                if file.precedence < first.precedence {
+                    *first = file;
+                }
+            })
+            .or_insert(file);
+    }
+
+    let mut by_name: BTreeMap<String, Vec<(&DesktopFile, &App)>> = BTreeMap::new();
+    for file in by_id.into_values() {
+        if let Some(app) = &file.app {
+            by_name
+                .entry(app.name.to_lowercase())
+                .or_default()
+                .push((file, app));
+        }
+    }
+
+    let mut listed = Vec::new();
+    for mut installs in by_name.into_values() {
+        installs.sort_by_key(|(file, _)| (file.precedence, &file.id));
+
+        let sources: HashSet<_> = installs.iter().map(|(file, _)| file.source).collect();
+        if sources.len() > 1 && sources.len() == installs.len() {
+            let (first, app) = installs[0];
+            listed.push(Listed {
+                id: &first.id,
+                app,
+                item: with_badge(first.source, app, true),
+                alternatives: installs[1..]
+                    .iter()
+                    .map(|(file, app)| with_badge(file.source, app, true))
+                    .collect(),
+            });
+        } else {
+            // probably different apps that happen to have the same name
+            listed.extend(installs.into_iter().map(|(file, app)| Listed {
+                id: &file.id,
+                app,
+                item: with_badge(file.source, app, false),
+                alternatives: Vec::new(),
+            }));
+        }
+    }
+    listed
+}
+
+/// Adds the source to the description of the app's item.
+///
+/// Most apps are from the system, so that is only shown when it's needed
+/// to tell installations apart.
+fn with_badge(source: Source, app: &App, always: bool) -> ListItem {
+    let mut item = app.item.clone();
+    if always || source != Source::System {
+        let badge = source.badge();
+        item.description = if item.description.is_empty() {
+            badge.to_string()
+        } else {
+            format!("{} · {badge}", item.description)
+        };
+    }
+    item
+}
@@ -0,0 +1,403 @@
+//! Parsing the `Exec` key of desktop entries.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    slice,
+};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, bail},
+};
+use freedesktop_desktop_entry::DesktopEntry;
+
+/// An `Exec` key, parsed mostly according to
+/// https://specifications.freedesktop.org/desktop-entry/latest/exec-variables.html
+///
+/// Field codes for files and URLs are kept so that they can be filled in when
+/// launching. All other field codes are expanded when parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exec {
+    args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Arg {
+    Text(String),
+    /// An argument containing `%f` or `%u`, split around the field code.
+    Single {
+        before: String,
+        kind: TargetKind,
+        after: String,
+    },
+    /// `%F` or `%U` on its own.
+    Multiple(TargetKind),
+}
+
+/// What an app can open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// Local files only (`%f` and `%F`).
+    File,
+    /// Local files or URLs (`%u` and `%U`).
+    Url,
+}
+
+impl Exec {
+    /// Parses an Exec key of the entry or one of its actions.
+    pub fn parse(
+        exec: Option<&str>,
+        entry: &DesktopEntry,
+        locales: &[impl AsRef<str>],
+    ) -> Result<Self> {
+        // entry.parse_exec() doesn't parse correctly (quoted args with spaces
+        // inside).
+        let exec =
+            shlex::split(exec.context("missing Exec key")?).context("failed to parse Exec key")?;
+
+        let mut args = Vec::new();
+        for arg in exec {
+            match arg.as_str() {
+                "%F" => args.push(Arg::Multiple(TargetKind::File)),
+                "%U" => args.push(Arg::Multiple(TargetKind::Url)),
+                // two arguments, or none if there is no icon
+                "%i" => {
+                    if let Some(icon) = entry.icon().filter(|icon| !icon.is_empty()) {
+                        args.push(Arg::Text("--icon".to_string()));
+                        args.push(Arg::Text(icon.to_string()));
+                    }
+                }
+                _ if !arg.contains('%') => args.push(Arg::Text(arg)),
+                _ => args.extend(parse_arg(&arg, entry, locales)?),
+            }
+        }
+
+        Ok(Self { args })
+    }
+
+    /// File name of the program that is run, skipping `env` and it's
+    /// variable assignments.
+    pub fn program_name(&self) -> Option<&str> {
+        let program = self
+            .args
+            .iter()
+            .map_while(|arg| match arg {
+                Arg::Text(text) => Some(text),
+                _ => None,
+            })
+            .find(|arg| *arg != "env" && !arg.contains('='))?;
+
+        Path::new(program).file_name()?.to_str()
+    }
+
+    /// What kind of targets the app can be launched with, if any.
+    pub fn target_kind(&self) -> Option<TargetKind> {
+        self.args.iter().find_map(|arg| match arg {
+            Arg::Text(_) => None,
+            Arg::Single { kind, .. } | Arg::Multiple(kind) => Some(*kind),
+        })
+    }
+
+    /// Expands the field codes, returning the commands that should be run to
+    /// open all of the `targets`.
+    ///
+    /// There is one command per target if the app can only open one file at a
+    /// time.
+    pub fn commands(&self, targets: &[String]) -> Result<Vec<Vec<String>>> {
+        if targets.is_empty() {
+            return Ok(vec![self.expand(&[])]);
+        }
+
+        let targets = self.resolve_targets(targets)?;
+        if self.args.iter().any(|arg| matches!(arg, Arg::Multiple(_))) {
+            Ok(vec![self.expand(&targets)])
+        } else {
+            Ok(targets
+                .iter()
+                .map(|target| self.expand(slice::from_ref(target)))
+                .collect())
+        }
+    }
+
+    /// Converts files or URLs that the user typed into what the app takes.
+    pub fn resolve_targets(&self, targets: &[String]) -> Result<Vec<String>> {
+        if targets.is_empty() {
+            return Ok(Vec::new());
+        }
+        let kind = self.target_kind().context("app can't open files or URLs")?;
+        targets
+            .iter()
+            .map(|target| resolve_target(target, kind))
+            .collect()
+    }
+
+    fn expand(&self, targets: &[String]) -> Vec<String> {
+        let mut argv = Vec::new();
+        for arg in &self.args {
+            match arg {
+                Arg::Text(text) => argv.push(text.clone()),
+                Arg::Multiple(_) => argv.extend(targets.iter().cloned()),
+                // without a target, the whole argument is removed: `firefox %u`
+                // becomes `firefox`, not `firefox ''`.
+                Arg::Single { before, after, .. } => {
+                    if let Some(target) = targets.first() {
+                        argv.push(format!("{before}{target}{after}"));
+                    }
+                }
+            }
+        }
+        argv
+    }
+}
+
+/// Parses a single argument that contains field codes.
+///
+/// Returns `None` if the argument is empty after expanding the field codes.
+fn parse_arg(arg: &str, entry: &DesktopEntry, locales: &[impl AsRef<str>]) -> Result<Option<Arg>> {
+    let mut parsed_arg = String::new();
+    // kind and byte index in `parsed_arg` of a %f or %u
+    let mut target = None;
+
+    let mut chars = arg.chars();
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            parsed_arg.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            // %% -> %
+            Some('%') => parsed_arg.push('%'),
+            // %F and %U should be on their own, but treat them like %f and %u
+            // if they aren't.
+            Some(code @ ('f' | 'F' | 'u' | 'U')) => {
+                if target.is_some() {
+                    bail!("Exec argument {arg:?} has more than one file or URL field code")
+                }
+                let kind = if code.eq_ignore_ascii_case(&'f') {
+                    TargetKind::File
+                } else {
+                    TargetKind::Url
+                };
+                target = Some((kind, parsed_arg.len()));
+            }
+            // ignore deprecated
+            Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+            // only valid as an argument on its own
+            Some('i') => {}
+            // translated name of the application
+            Some('c') => parsed_arg.push_str(&entry.name(locales).context("missing Name key")?),
+            // location of the desktop file
+            Some('k') => parsed_arg.push_str(
+                entry
+                    .path
+                    .to_str()
+                    .context("desktop file path is not UTF-8")?,
+            ),
+            Some(char) => bail!("unknown field code %{char}"),
+            // a trailing % isn't valid, but is probably meant literally
+            None => parsed_arg.push('%'),
+        }
+    }
+
+    Ok(match target {
+        Some((kind, index)) => {
+            let after = parsed_arg.split_off(index);
+            Some(Arg::Single {
+                before: parsed_arg,
+                kind,
+                after,
+            })
+        }
+        None if parsed_arg.is_empty() => None,
+        None => Some(Arg::Text(parsed_arg)),
+    })
+}
+
+/// Splits a user-provided list of files or URLs, allowing quotes around paths
+/// with spaces.
+pub fn split_targets(input: &str) -> Option<Vec<String>> {
+    shlex::split(input)
+}
+
+/// Converts a file or URL that the user typed into something that can be
+/// passed to an app.
+///
+/// Paths are resolved relative to the home directory.
+fn resolve_target(target: &str, kind: TargetKind) -> Result<String> {
+    let is_url = target.contains("://") || target.starts_with("mailto:");
+
+    let path = match (kind, target.strip_prefix("file://")) {
+        (TargetKind::File, Some(url)) => return file_url_path(url),
+        (TargetKind::File, None) if is_url => bail!("app can only open local files, not {target}"),
+        (TargetKind::Url, _) if is_url => return Ok(target.to_string()),
+        (_, _) => target,
+    };
+
+    let home = PathBuf::from(env::var_os("HOME").context("HOME variable must be set")?);
+    let path = match path.strip_prefix('~') {
+        Some("") => home,
+        Some(rest) if rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        // `~user` or relative paths
+        _ => home.join(path),
+    };
+
+    Ok(path.to_str().context("file path is not UTF-8")?.to_string())
+}
+
+/// The path of a `file://` URL, after the `file://`. The host has to be empty
+/// or `localhost`, since other computers' files can't be opened.
+fn file_url_path(url: &str) -> Result<String> {
+    let path = match url.find('/') {
+        Some(0) => url,
+        Some(i) if &url[..i] == "localhost" => &url[i..],
+        _ => bail!("app can only open local files, not file://{url}"),
+    };
+
+    // decode escapes like `%20`
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        match after.get(..2) {
+            Some(&[high, low]) if byte == b'%' => {
+                let hex = |digit: u8| char::from(digit).to_digit(16);
+                let (Some(high), Some(low)) = (hex(high), hex(low)) else {
+                    bail!("invalid escape in file://{url}");
+                };
+                bytes.push((high * 16 + low) as u8);
+                rest = &after[2..];
+            }
+            _ if byte == b'%' => bail!("invalid escape in file://{url}"),
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8(bytes).context("file path is not UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file_name: &str) -> DesktopEntry {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/share/applications")
+            .join(file_name);
+        DesktopEntry::from_path(path, Some(&["en"][..])).unwrap()
+    }
+
+    fn exec(file_name: &str) -> Exec {
+        let entry = entry(file_name);
+        Exec::parse(entry.exec(), &entry, &["en"]).unwrap()
+    }
+
+    fn home(path: &str) -> String {
+        format!("{}/{path}", env::var("HOME").unwrap())
+    }
+
+    #[test]
+    fn without_targets() {
+        assert_eq!(exec("gimp.desktop").commands(&[]).unwrap(), [["gimp-2.10"]]);
+        assert_eq!(
+            exec("image-viewer.desktop").commands(&[]).unwrap(),
+            [["image-viewer", "--fullscreen"]]
+        );
+        assert_eq!(
+            exec("calculator.desktop").commands(&[]).unwrap(),
+            [["calculator"]]
+        );
+    }
+
+    #[test]
+    fn multiple_files_in_one_command() {
+        assert_eq!(
+            exec("gimp.desktop")
+                .commands(&["~/a.png".to_string(), "/tmp/b c.png".to_string()])
+                .unwrap(),
+            [vec![
+                "gimp-2.10".to_string(),
+                home("a.png"),
+                "/tmp/b c.png".to_string()
+            ]]
+        );
+    }
+
+    #[test]
+    fn single_file_per_command() {
+        assert_eq!(
+            exec("image-viewer.desktop")
+                .commands(&["a.png".to_string(), "file:///tmp/b.png".to_string()])
+                .unwrap(),
+            [
+                vec![
+                    "image-viewer".to_string(),
+                    "--fullscreen".to_string(),
+                    format!("--open={}", home("a.png")),
+                ],
+                vec![
+                    "image-viewer".to_string(),
+                    "--fullscreen".to_string(),
+                    "--open=/tmp/b.png".to_string(),
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn file_urls() {
+        let gimp = exec("gimp.desktop");
+        let open = |url: &str| gimp.commands(&[url.to_string()]);
+        assert_eq!(
+            open("file:///tmp/My%20File%C3%A9.png").unwrap(),
+            [["gimp-2.10", "/tmp/My Fileé.png"]]
+        );
+        assert_eq!(
+            open("file://localhost/tmp/a.png").unwrap(),
+            [["gimp-2.10", "/tmp/a.png"]]
+        );
+        assert!(open("file://host/tmp/a.png").is_err());
+        assert!(open("file://a.png").is_err());
+        assert!(open("file:///tmp/100%.png").is_err());
+    }
+
+    #[test]
+    fn urls() {
+        let browser = exec("browser.desktop");
+        assert_eq!(browser.target_kind(), Some(TargetKind::Url));
+        assert_eq!(
+            browser
+                .commands(&["https://example.com".to_string(), "~".to_string()])
+                .unwrap(),
+            [
+                ["browser", "--new-tab", "https://example.com"],
+                ["browser", "--new-tab", &env::var("HOME").unwrap()],
+            ]
+        );
+
+        // can't give URLs to apps that only take files
+        assert!(
+            exec("gimp.desktop")
+                .commands(&["https://example.com/a.png".to_string()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn no_targets_allowed() {
+        let calculator = exec("calculator.desktop");
+        assert_eq!(calculator.target_kind(), None);
+        assert!(calculator.commands(&["a.txt".to_string()]).is_err());
+    }
+
+    #[test]
+    fn split_quoted_targets() {
+        assert_eq!(
+            split_targets(r#"~/a.png "my file.png" b\ c.png"#).unwrap(),
+            ["~/a.png", "my file.png", "b c.png"]
+        );
+        assert_eq!(split_targets(r#""unclosed"#), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Deciding which desktop entries are shown.
+
+use covey_plugin::{Result, anyhow::Context};
+use freedesktop_desktop_entry::DesktopEntry;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{Config, window};
+
+/// Rules from the config for which desktop entries to show.
+#[derive(Debug, Clone)]
+pub struct EntryFilter {
+    include_ids: GlobSet,
+    exclude_ids: GlobSet,
+    include_categories: Vec<String>,
+    exclude_categories: Vec<String>,
+    show_no_display: bool,
+    require_icon: bool,
+    show_hidden: bool,
+    check_try_exec: bool,
+    check_show_in: bool,
+    current_desktops: Vec<String>,
+}
+
+impl EntryFilter {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            include_ids: glob_set(&config.include_ids).context("invalid include-ids glob")?,
+            exclude_ids: glob_set(&config.exclude_ids).context("invalid exclude-ids glob")?,
+            include_categories: split_list(&config.include_categories),
+            exclude_categories: split_list(&config.exclude_categories),
+            show_no_display: config.show_no_display,
+            require_icon: config.require_icon,
+            show_hidden: config.show_hidden,
+            check_try_exec: config.check_try_exec,
+            check_show_in: config.check_show_in,
+            current_desktops: window::current_desktops(),
+        })
+    }
+
+    /// Whether the entry should be listed.
+    ///
+    /// Entries that aren't available on this system (`Hidden`, a missing
+    /// `TryExec`, or excluded by `OnlyShowIn`/`NotShowIn`) are always hidden.
+    /// Otherwise, the id and category include rules override the rest of the
+    /// filters.
+    pub fn allows(&self, entry: &DesktopEntry) -> bool {
+        if !self.is_available(entry) || self.exclude_ids.is_match(entry.id()) {
+            return false;
+        }
+
+        let categories: Vec<_> = entry
+            .categories()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|cat| !cat.is_empty())
+            .collect();
+
+        if self.include_ids.is_match(entry.id())
+            || categories
+                .iter()
+                .any(|cat| contains(&self.include_categories, cat))
+        {
+            return true;
+        }
+
+        // probably irrelevant if all of it's categories are excluded
+        let excluded_by_category = !self.exclude_categories.is_empty()
+            && categories
+                .iter()
+                .all(|cat| contains(&self.exclude_categories, cat));
+
+        (self.show_no_display || !entry.no_display())
+            && (!self.require_icon || entry.icon().is_some_and(|icon| !icon.is_empty()))
+            && !excluded_by_category
+    }
+
+    /// Checks the keys that the spec says should hide an entry.
+    fn is_available(&self, entry: &DesktopEntry) -> bool {
+        if entry.hidden() && !self.show_hidden {
+            return false;
+        }
+
+        if self.check_try_exec
+            && entry
+                .try_exec()
+                .is_some_and(|try_exec| which::which(try_exec).is_err())
+        {
+            return false;
+        }
+
+        if self.check_show_in {
+            let on_current_desktop = |desktops: Vec<&str>| {
+                desktops
+                    .into_iter()
+                    .any(|desktop| contains(&self.current_desktops, desktop))
+            };
+
+            if entry.only_show_in().is_some_and(|d| !on_current_desktop(d))
+                || entry.not_show_in().is_some_and(on_current_desktop)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Splits a `;` separated config option, like the lists in desktop files.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn glob_set(globs: &str) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in split_list(globs) {
+        builder.add(Glob::new(&glob)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn contains(list: &[String], item: &str) -> bool {
+    list.iter().any(|s| s.eq_ignore_ascii_case(item))
+}
@@ -0,0 +1,404 @@
+//! Starting apps, optionally through a terminal or a wrapper command.
+
+use std::{
+    env,
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, bail},
+    spawn,
+};
+use freedesktop_desktop_entry::DesktopEntry;
+
+use crate::{Config, exec::Exec, launch_method::LaunchMethodSelection};
+
+/// How apps are started, from the config.
+#[derive(Debug, Clone)]
+pub struct Launcher {
+    method: Method,
+    /// Command to run terminal apps in, which the app's command is appended
+    /// to.
+    terminal: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+enum Method {
+    /// As a child process of the plugin.
+    Direct,
+    /// In a new systemd scope, so that the app gets it's own cgroup.
+    SystemdRun,
+    /// Prefixed with a user-defined command.
+    Wrapper(Vec<String>),
+    /// By desktop file id with `gtk-launch`, which reads the entry itself.
+    /// Actions can't be launched this way, so they're started directly.
+    GtkLaunch {
+        /// The desktop file directories in order of precedence, to find the
+        /// file that gtk-launch starts for an id.
+        dirs: Vec<PathBuf>,
+    },
+}
+
+/// A desktop entry or one of it's actions, ready to be launched.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub exec: Exec,
+    /// Desktop file id, used to name systemd scopes.
+    id: String,
+    /// The desktop file.
+    path: PathBuf,
+    /// Whether this is one of the entry's actions rather than the entry.
+    is_action: bool,
+    /// The `Path` key.
+    working_dir: Option<String>,
+    /// The `Terminal` key.
+    terminal: bool,
+}
+
+impl Program {
+    /// Uses the rest of the entry's keys to decide how to run `exec`, which
+    /// is the Exec of the entry or of one of its actions.
+    pub fn new(entry: &DesktopEntry, exec: Exec, is_action: bool) -> Self {
+        Self {
+            exec,
+            id: entry.id().to_string(),
+            path: entry.path.clone(),
+            is_action,
+            working_dir: entry
+                .path()
+                .filter(|path| !path.is_empty())
+                .map(str::to_string),
+            terminal: entry.terminal(),
+        }
+    }
+}
+
+impl Launcher {
+    /// `dirs` are the desktop file directories, in order of precedence.
+    pub fn from_config(config: &Config, dirs: &[PathBuf]) -> Result<Self> {
+        let method = match config.launch_method {
+            LaunchMethodSelection::Direct => Method::Direct,
+            LaunchMethodSelection::SystemdRun => Method::SystemdRun,
+            LaunchMethodSelection::Wrapper => {
+                let wrapper = split_command(&config.launch_wrapper)
+                    .context("invalid launch-wrapper command")?;
+                if wrapper.is_empty() {
+                    bail!("launch-wrapper must be set to use the wrapper launch method")
+                }
+                Method::Wrapper(wrapper)
+            }
+            LaunchMethodSelection::GtkLaunch => Method::GtkLaunch {
+                dirs: dirs.to_vec(),
+            },
+        };
+
+        let terminal = if config.terminal.trim().is_empty() {
+            default_terminal()
+        } else {
+            Some(split_command(&config.terminal).context("invalid terminal command")?)
+        };
+
+        Ok(Self { method, terminal })
+    }
+
+    /// Runs the program with files or URLs to open.
+    pub fn launch(&self, program: &Program, targets: &[String]) -> Result<()> {
+        if let Some(argv) = self.gtk_launch(program, targets)? {
+            spawn::command(&argv[0], &argv[1..])?;
+            return Ok(());
+        }
+
+        for argv in program.exec.commands(targets)? {
+            let argv = self.wrap(program, argv)?;
+            let (program, args) = argv.split_first().context("missing Exec command")?;
+            spawn::command(program, args)?;
+        }
+        Ok(())
+    }
+
+    /// The `gtk-launch` command for the program, if it should be launched
+    /// with it. gtk-launch handles the terminal and working directory itself.
+    fn gtk_launch(&self, program: &Program, targets: &[String]) -> Result<Option<Vec<String>>> {
+        let Method::GtkLaunch { dirs } = &self.method else {
+            return Ok(None);
+        };
+
+        // gtk-launch would start the first file with the id instead of
+        // another installation of it
+        let file_name = format!("{}.desktop", program.id);
+        let is_first = dirs
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.exists())
+            .is_some_and(|path| path == program.path);
+        if program.is_action || !is_first {
+            return Ok(None);
+        }
+
+        let mut argv = vec!["gtk-launch".to_string(), program.id.clone()];
+        argv.extend(program.exec.resolve_targets(targets)?);
+        Ok(Some(argv))
+    }
+
+    /// Adds the terminal, working directory and launch method to an
+    /// expanded Exec command.
+    fn wrap(&self, program: &Program, argv: Vec<String>) -> Result<Vec<String>> {
+        let mut wrapped = match &self.method {
+            Method::Direct | Method::GtkLaunch { .. } => Vec::new(),
+            Method::SystemdRun => vec![
+                "systemd-run".to_string(),
+                "--user".to_string(),
+                "--scope".to_string(),
+                "--quiet".to_string(),
+                "--slice=app.slice".to_string(),
+                format!("--unit={}", scope_name(&program.id)),
+                "--".to_string(),
+            ],
+            Method::Wrapper(wrapper) => wrapper.clone(),
+        };
+
+        if let Some(dir) = &program.working_dir {
+            wrapped.extend(["env".to_string(), "-C".to_string(), dir.clone()]);
+        }
+
+        if program.terminal {
+            let terminal = self
+                .terminal
+                .as_ref()
+                .context("app needs a terminal, but no terminal is configured")?;
+            wrapped.extend(terminal.iter().cloned());
+        }
+
+        wrapped.extend(argv);
+        Ok(wrapped)
+    }
+}
+
+fn split_command(command: &str) -> Result<Vec<String>> {
+    shlex::split(command).context("unclosed quote")
+}
+
+/// Uses `xdg-terminal-exec` if it's installed, otherwise `$TERMINAL -e`.
+fn default_terminal() -> Option<Vec<String>> {
+    if which::which("xdg-terminal-exec").is_ok() {
+        Some(vec!["xdg-terminal-exec".to_string()])
+    } else {
+        env::var("TERMINAL")
+            .ok()
+            .filter(|terminal| !terminal.is_empty())
+            .map(|terminal| vec![terminal, "-e".to_string()])
+    }
+}
+
+/// A unique scope name following the `app-<launcher>-<app id>-<random>.scope`
+/// convention from
+/// https://systemd.io/DESKTOP_ENVIRONMENTS/
+fn scope_name(id: &str) -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!(
+        "app-covey-{}-{}_{count}.scope",
+        systemd_escape(id),
+        std::process::id()
+    )
+}
+
+/// Escapes characters that aren't allowed in unit names, like
+/// `systemd-escape` does. `-` is also escaped since it separates the parts of
+/// the scope name.
+fn systemd_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for (i, byte) in s.bytes().enumerate() {
+        if byte.is_ascii_alphanumeric() || byte == b':' || byte == b'_' || (byte == b'.' && i > 0) {
+            escaped.push(char::from(byte));
+        } else {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn applications(data_dir: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(data_dir)
+            .join("applications")
+    }
+
+    fn program_from(path: PathBuf) -> Program {
+        let entry = DesktopEntry::from_path(path, Some(&["en"][..])).unwrap();
+        let exec = Exec::parse(entry.exec(), &entry, &["en"]).unwrap();
+        Program::new(&entry, exec, false)
+    }
+
+    fn program(working_dir: Option<&str>, terminal: bool) -> Program {
+        Program {
+            working_dir: working_dir.map(str::to_string),
+            terminal,
+            ..program_from(applications("share").join("gimp.desktop"))
+        }
+    }
+
+    fn home(path: &str) -> String {
+        format!("{}/{path}", env::var("HOME").unwrap())
+    }
+
+    fn launcher(method: Method) -> Launcher {
+        Launcher {
+            method,
+            terminal: Some(vec!["foot".to_string(), "-e".to_string()]),
+        }
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn wrapping_order() {
+        let terminal_app = program(Some("/tmp/work dir"), true);
+        let exec = argv(&["gimp-2.10", "a.png"]);
+
+        let wrapped = launcher(Method::SystemdRun)
+            .wrap(&terminal_app, exec.clone())
+            .unwrap();
+        assert_eq!(
+            wrapped[..5],
+            argv(&[
+                "systemd-run",
+                "--user",
+                "--scope",
+                "--quiet",
+                "--slice=app.slice"
+            ])
+        );
+        assert!(wrapped[5].starts_with("--unit=app-covey-gimp-"));
+        assert_eq!(
+            wrapped[6..],
+            argv(&[
+                "--",
+                "env",
+                "-C",
+                "/tmp/work dir",
+                "foot",
+                "-e",
+                "gimp-2.10",
+                "a.png"
+            ])
+        );
+
+        let wrapper = Method::Wrapper(argv(&["uwsm", "app", "--"]));
+        assert_eq!(
+            launcher(wrapper).wrap(&terminal_app, exec.clone()).unwrap(),
+            argv(&[
+                "uwsm",
+                "app",
+                "--",
+                "env",
+                "-C",
+                "/tmp/work dir",
+                "foot",
+                "-e",
+                "gimp-2.10",
+                "a.png"
+            ])
+        );
+
+        assert_eq!(
+            launcher(Method::Direct)
+                .wrap(&program(None, false), exec.clone())
+                .unwrap(),
+            exec
+        );
+    }
+
+    #[test]
+    fn terminal_apps_without_a_terminal() {
+        let launcher = Launcher {
+            method: Method::Direct,
+            terminal: None,
+        };
+        assert!(
+            launcher
+                .wrap(&program(None, true), argv(&["htop"]))
+                .is_err()
+        );
+        assert!(
+            launcher
+                .wrap(&program(None, false), argv(&["gimp"]))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn gtk_launch() {
+        let gtk_launch = launcher(Method::GtkLaunch {
+            dirs: vec![applications("home"), applications("share")],
+        });
+        let mut program = program(Some("/tmp"), true);
+        assert_eq!(
+            gtk_launch
+                .gtk_launch(&program, &argv(&["~/a.png", "b.png"]))
+                .unwrap(),
+            Some(vec![
+                "gtk-launch".to_string(),
+                "gimp".to_string(),
+                home("a.png"),
+                home("b.png"),
+            ])
+        );
+        assert!(
+            gtk_launch
+                .gtk_launch(&program, &argv(&["https://example.com"]))
+                .is_err()
+        );
+
+        // the calculator in the home dir is the one gtk-launch would start
+        let calculator = program_from(applications("share").join("calculator.desktop"));
+        assert_eq!(gtk_launch.gtk_launch(&calculator, &[]).unwrap(), None);
+
+        // actions are started directly
+        program.is_action = true;
+        assert_eq!(gtk_launch.gtk_launch(&program, &[]).unwrap(), None);
+        assert_eq!(
+            gtk_launch.wrap(&program, argv(&["gimp-2.10"])).unwrap(),
+            argv(&["env", "-C", "/tmp", "foot", "-e", "gimp-2.10"])
+        );
+
+        program.is_action = false;
+        assert_eq!(
+            launcher(Method::Direct).gtk_launch(&program, &[]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn scope_names() {
+        let prefix = |id| format!("app-covey-{id}-{}_", std::process::id());
+
+        let name = scope_name("org.gnome.Nautilus");
+        assert!(name.starts_with(&prefix("org.gnome.Nautilus")));
+        assert!(name.ends_with(".scope"));
+        assert_ne!(scope_name("org.gnome.Nautilus"), name);
+
+        assert!(scope_name("avahi-discover").starts_with(&prefix(r"avahi\x2ddiscover")));
+        assert!(scope_name("café").starts_with(&prefix(r"caf\xc3\xa9")));
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(systemd_escape("org.gimp.GIMP"), "org.gimp.GIMP");
+        assert_eq!(systemd_escape("a:b_c"), "a:b_c");
+        assert_eq!(systemd_escape(".hidden"), r"\x2ehidden");
+        assert_eq!(systemd_escape("a b/c-d"), r"a\x20b\x2fc\x2dd");
+        assert_eq!(systemd_escape("日"), r"\xe6\x97\xa5");
+    }
+}
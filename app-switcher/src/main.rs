@@ -1,180 +1,799 @@
-use std::{process::Stdio, sync::LazyLock};
+mod dedupe;
+mod exec;
+mod filter;
+mod launch;
+mod pins;
+mod search;
+mod watch;
+mod window;
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 use covey_plugin::{
-    Icon, List, ListItem, Plugin, Result,
-    anyhow::{Context, anyhow, bail},
-    clone_async, rank, spawn,
+    Icon, Input, List, ListItem, ListSection, Plugin, Result,
+    anyhow::{Context, anyhow},
+    clone_async, rank,
 };
+use dedupe::{Listed, Source};
+use exec::{Exec, split_targets};
+use filter::EntryFilter;
 use freedesktop_desktop_entry::{self as desktop, DesktopEntry};
+use launch::{Launcher, Program};
+use pins::Pins;
+use search::{SearchTerms, SearchWeights};
+use tokio::sync::RwLock;
+use window::{Window, WindowBackend};
 
 covey_plugin::include_manifest!();
 
 struct AppSwitcher {
-    entries: Vec<ListItem>,
-}
-
-fn process_entry(entry: DesktopEntry, locales: &[impl AsRef<str>]) -> Option<ListItem> {
-    // filter out some desktop entries that are probably irrelevant
-    // has NoDisplay, or no Icon attribute, or all it's categories are useless.
-    if entry.no_display()
-        || entry.icon().is_none()
-        || entry.categories().is_none_or(|cats| {
-            cats.into_iter()
-                .filter(|cat| !USELESS_CATEGORIES.contains(cat))
-                .filter(|cat| !cat.is_empty())
-                .count()
-                == 0
-        })
-    {
+    apps: Arc<RwLock<Apps>>,
+    launcher: Launcher,
+    search_weights: SearchWeights,
+    /// How many recently launched apps to show before the others when the
+    /// query is empty.
+    recent_apps: usize,
+    pins: Pins,
+    /// `None` if windows can't be listed on this desktop.
+    window_backend: Option<WindowBackend>,
+    /// Keeps `apps` up to date until dropped. `None` if the desktop file
+    /// directories couldn't be watched.
+    _watcher: Option<watch::DirWatcher>,
+}
+
+/// Every desktop file, by it's path.
+type Apps = BTreeMap<PathBuf, DesktopFile>;
+
+struct DesktopFile {
+    id: String,
+    source: Source,
+    /// Index of the data dir that the file is in. Lower is preferred.
+    precedence: usize,
+    /// `None` if the entry is filtered out or invalid.
+    app: Option<App>,
+}
+
+/// A desktop entry that can be launched.
+struct App {
+    name: String,
+    /// Lowercase WM class of the app's windows.
+    class: String,
+    icon: Option<String>,
+    /// `Err` with a message if the Exec key is invalid.
+    program: std::result::Result<Program, String>,
+    search_terms: SearchTerms,
+    item: ListItem,
+    /// Items for each `[Desktop Action]`, shown after completing the app
+    /// name.
+    actions: Vec<ListItem>,
+}
+
+fn process_entry(
+    entry: DesktopEntry,
+    locales: &[impl AsRef<str>],
+    filter: &EntryFilter,
+    launcher: &Launcher,
+    window_backend: &Option<WindowBackend>,
+) -> Option<App> {
+    if !filter.allows(&entry) {
         return None;
     }
 
-    // entry.parse_exec() doesn't parse correctly (quoted args with spaces inside).
-    let exec = parse_exec(&entry, locales)
+    let program = Exec::parse(entry.exec(), &entry, locales)
+        .map(|exec| Program::new(&entry, exec, false))
         .context("failed to parse app Exec")
         .map_err(|e| format!("{e:#}"));
-    let class = entry.startup_wm_class().unwrap_or(entry.id()).to_string();
+    let class = entry
+        .startup_wm_class()
+        .unwrap_or(entry.id())
+        .to_lowercase();
+    let name = entry.name(locales)?.into_owned();
+    let search_terms = SearchTerms::new(
+        &entry,
+        program.as_ref().ok().map(|program| &program.exec),
+        locales,
+    );
+    let icon = entry.icon().map(str::to_string);
 
-    Some(
-        ListItem::new(entry.name(locales)?)
-            .with_description(entry.comment(locales).unwrap_or_default())
-            .with_icon(entry.icon().map(|name| Icon::Name(name.to_string())))
-            .on_activate(clone_async!(class, exec, |menu| {
+    let item = ListItem::new(&name)
+        .with_description(entry.comment(locales).unwrap_or_default())
+        .with_icon(icon.clone().map(Icon::Name))
+        .on_complete(clone_async!(name, |menu| {
+            menu.set_input(Input::new(format!("{name}: ")));
+            Ok(())
+        }))
+        .on_activate(clone_async!(
+            class,
+            program,
+            launcher,
+            window_backend,
+            |menu| {
                 menu.close();
-                if class.is_empty() || activate_kdotool(&class).await.is_err() {
-                    let exec = exec.map_err(|s| anyhow!(s))?;
-                    let (program, args) = exec.split_first().context("missing Exec command")?;
-                    spawn::command(program, args)?;
+                let activated = match &window_backend {
+                    Some(backend) if !class.is_empty() => {
+                        backend.activate_class(&class).await.is_ok()
+                    }
+                    _ => false,
+                };
+                if !activated {
+                    launch(&launcher, program, &[])?;
                 }
 
                 Ok(())
-            })),
-    )
+            }
+        ));
+
+    let actions = entry
+        .actions()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|action| !action.is_empty())
+        .filter_map(|action| {
+            let program = Exec::parse(entry.action_exec(action), &entry, locales)
+                .map(|exec| Program::new(&entry, exec, true))
+                .with_context(|| format!("failed to parse Exec of action {action}"))
+                .map_err(|e| format!("{e:#}"));
+
+            Some(
+                ListItem::new(entry.action_name(action, locales)?)
+                    .with_description(&name)
+                    .with_icon(
+                        entry
+                            .action_entry(action, "Icon")
+                            .or(icon.as_deref())
+                            .map(|name| Icon::Name(name.to_string())),
+                    )
+                    .on_activate(clone_async!(program, launcher, |menu| {
+                        menu.close();
+                        launch(&launcher, program, &[])
+                    })),
+            )
+        })
+        .collect();
+
+    Some(App {
+        name,
+        class,
+        icon,
+        program,
+        search_terms,
+        item,
+        actions,
+    })
 }
 
-impl Plugin for AppSwitcher {
-    type Config = ();
+impl App {
+    /// An item that launches the app with the files or URLs in `targets`.
+    fn open_targets_item(&self, launcher: &Launcher, targets: &str) -> ListItem {
+        ListItem::new(format!("Open {targets}"))
+            .with_description(&self.name)
+            .with_icon(self.icon.clone().map(Icon::Name))
+            .on_activate(clone_async!(
+                targets,
+                launcher,
+                program = self.program,
+                |menu| {
+                    let targets = split_targets(&targets).context("unclosed quote in file list")?;
+                    menu.close();
+                    launch(&launcher, program, &targets)
+                }
+            ))
+    }
+}
 
-    async fn new(_: ()) -> Result<Self> {
-        let locales = desktop::get_languages_from_env();
-        let mut entries = Vec::new();
-        for entry in desktop::Iter::new(desktop::default_paths()).entries(Some(&locales)) {
-            entries.extend(process_entry(entry, &locales));
-        }
+/// Everything needed to turn desktop files into [`App`]s.
+#[derive(Clone)]
+struct Loader {
+    /// The desktop file directories, in order of precedence.
+    dirs: Vec<PathBuf>,
+    locales: Vec<String>,
+    filter: EntryFilter,
+    launcher: Launcher,
+    window_backend: Option<WindowBackend>,
+}
 
-        Ok(Self { entries })
+impl Loader {
+    /// Uses the XDG data dirs and locales of the environment.
+    fn from_env(config: &Config, window_backend: Option<WindowBackend>) -> Result<Self> {
+        Self::new(
+            config,
+            desktop::default_paths(),
+            desktop::get_languages_from_env(),
+            window_backend,
+        )
     }
 
-    async fn query(&self, query: String) -> Result<List> {
-        Ok(List::new(
-            rank::rank(&query, &self.entries, rank::Weights::with_history()).await,
-        ))
-    }
-}
-
-/// Tries to open the window using kdotool, returning `Err` if it fails.
-async fn activate_kdotool(class: &str) -> Result<()> {
-    let output = tokio::process::Command::new(&*KDOTOOL_PATH)
-        .arg("search")
-        .arg("--limit")
-        .arg("1")
-        .arg("--class")
-        .arg(class)
-        .stdout(Stdio::piped())
-        .spawn()?
-        .wait_with_output()
-        .await?;
-
-    // prints an empty string if nothing matches
-    if output.stdout.is_empty() {
-        bail!("window not found")
-    };
+    /// `dirs` are the desktop file directories, in order of precedence.
+    fn new(
+        config: &Config,
+        dirs: impl IntoIterator<Item = PathBuf>,
+        locales: Vec<String>,
+        window_backend: Option<WindowBackend>,
+    ) -> Result<Self> {
+        // desktop file paths are canonical
+        let dirs: Vec<_> = dirs
+            .into_iter()
+            .map(|dir| dir.canonicalize().unwrap_or(dir))
+            .collect();
+        Ok(Self {
+            locales,
+            filter: EntryFilter::from_config(config)?,
+            launcher: Launcher::from_config(config, &dirs)?,
+            dirs,
+            window_backend,
+        })
+    }
 
-    let exit = tokio::process::Command::new(&*KDOTOOL_PATH)
-        .arg("windowactivate")
-        .arg(String::from_utf8(output.stdout)?.trim())
-        .spawn()?
-        .wait()
-        .await?;
+    /// Loads every desktop file in the directories and their subdirectories.
+    fn load_dirs(&self, dirs: impl IntoIterator<Item = PathBuf>) -> Apps {
+        desktop::Iter::new(dirs.into_iter())
+            .entries(Some(&self.locales))
+            .filter_map(|entry| self.load(entry))
+            .collect()
+    }
 
-    if exit.success() {
-        Ok(())
-    } else {
-        bail!("kdotool failed to activate: {:?}", exit)
+    fn load_file(&self, path: PathBuf) -> Option<(PathBuf, DesktopFile)> {
+        if path.extension().is_none_or(|ext| ext != "desktop") {
+            return None;
+        }
+        self.load(DesktopEntry::from_path(path, Some(&self.locales)).ok()?)
     }
+
+    fn load(&self, entry: DesktopEntry) -> Option<(PathBuf, DesktopFile)> {
+        let path = entry.path.clone();
+        let file = DesktopFile {
+            id: entry.id().to_string(),
+            source: Source::guess_from(&path),
+            precedence: self
+                .dirs
+                .iter()
+                .position(|dir| path.starts_with(dir))
+                .unwrap_or(usize::MAX),
+            app: process_entry(
+                entry,
+                &self.locales,
+                &self.filter,
+                &self.launcher,
+                &self.window_backend,
+            ),
+        };
+        Some((path, file))
+    }
+
+    /// Keeps `apps` up to date with their desktop files until the watcher
+    /// is dropped.
+    async fn reload_on_change(self, apps: Arc<RwLock<Apps>>, mut changes: watch::Changes) {
+        while let Some(paths) = changes.next().await {
+            // read the new files before locking so that queries aren't blocked
+            let reloaded: Vec<_> = paths
+                .iter()
+                .map(|path| {
+                    if path.is_dir() {
+                        self.load_dirs([path.clone()])
+                    } else {
+                        self.load_file(path.clone()).into_iter().collect()
+                    }
+                })
+                .collect();
+
+            let mut apps = apps.write().await;
+            for (path, reloaded) in paths.iter().zip(reloaded) {
+                // a path could be a directory that was removed
+                apps.retain(|app_path, _| !app_path.starts_with(path));
+                apps.extend(reloaded);
+            }
+        }
+    }
+}
+
+/// Launches a program from [`App::program`], opening the files or URLs in
+/// `targets`.
+fn launch(
+    launcher: &Launcher,
+    program: std::result::Result<Program, String>,
+    targets: &[String],
+) -> Result<()> {
+    launcher.launch(&program.map_err(|s| anyhow!(s))?, targets)
 }
 
-/// Parses the Exec entry mostly according to
-/// https://specifications.freedesktop.org/desktop-entry/latest/exec-variables.html
-fn parse_exec(entry: &DesktopEntry, locales: &[impl AsRef<str>]) -> Result<Vec<String>> {
-    let exec = shlex::split(entry.exec().context("missing Exec key")?)
-        .context("failed to parse Exec key")?;
+impl Plugin for AppSwitcher {
+    type Config = Config;
+
+    async fn new(config: Config) -> Result<Self> {
+        let window_backend = WindowBackend::from_config(&config.window_backend);
+        let loader = Loader::from_env(&config, window_backend.clone())?;
+        let launcher = loader.launcher.clone();
+
+        let apps = Arc::new(RwLock::new(loader.load_dirs(loader.dirs.clone())));
+
+        let watcher = match watch::watch(loader.dirs.clone()) {
+            Ok((watcher, changes)) => {
+                tokio::spawn(loader.reload_on_change(Arc::clone(&apps), changes));
+                Some(watcher)
+            }
+            Err(e) => {
+                eprintln!("failed to watch desktop files: {e:#}");
+                None
+            }
+        };
+
+        Ok(Self {
+            apps,
+            launcher,
+            search_weights: SearchWeights::from_config(&config),
+            recent_apps: config.recent_apps.try_into().unwrap_or(0),
+            pins: Pins::load(covey_plugin::plugin_data_dir().join("pins.json")).await,
+            window_backend,
+            _watcher: watcher,
+        })
+    }
 
-    let mut parsed_exec = Vec::new();
+    async fn query(&self, query: String) -> Result<List> {
+        let apps = self.apps.read().await;
+        let listed: Vec<_> = dedupe::dedupe(&apps)
+            .into_iter()
+            .map(|listed| with_pin_commands(&self.pins, listed, &query))
+            .collect();
 
-    for arg in exec {
-        if !arg.contains('%') {
-            parsed_exec.push(arg);
-            continue;
+        if let Some(rest) = query.strip_prefix(HIDDEN_QUERY) {
+            let hidden = listed
+                .iter()
+                .filter(|listed| self.pins.is_hidden(listed.id))
+                .map(search_entry);
+            return Ok(List::new(search::rank(
+                rest.trim_start(),
+                hidden,
+                self.search_weights,
+            )));
         }
 
-        let mut parsed_arg = String::new();
-        let mut after_percent = false;
-        for char in arg.chars() {
-            if after_percent {
-                match char {
-                    // %% -> %
-                    '%' => parsed_arg.push('%'),
-                    // ignore extra files or uris
-                    'f' | 'F' | 'u' | 'U' => {}
-                    // ignore deprecated
-                    'd' | 'D' | 'n' | 'N' | 'v' | 'm' => {}
-                    // icon -- I don't understand what the spec means so ignoring
-                    'i' => {}
-                    // translated name of the application
-                    'c' => parsed_arg.push_str(&entry.name(locales).context("missing Name key")?),
-                    // location of the desktop file
-                    'k' => parsed_arg.push_str(
-                        entry
-                            .path
-                            .to_str()
-                            .context("desktop file path is not UTF-8")?,
-                    ),
-                    _ => bail!("unknown field code %{char}"),
-                }
-            } else {
-                match char {
-                    '%' => after_percent = true,
-                    _ => parsed_arg.push(char),
-                }
+        let shown: Vec<_> = listed
+            .iter()
+            .filter(|listed| !self.pins.is_hidden(listed.id))
+            .collect();
+
+        if let Some((listed, rest)) = completed_app(&shown, &query) {
+            let app = listed.app;
+            // other installations can be picked after the app's actions
+            let actions = app.actions.iter().chain(&listed.alternatives);
+            let mut items = rank::rank(rest, actions, rank::Weights::with_history()).await;
+            // anything typed after the app name might be files to open it with
+            if !rest.is_empty()
+                && matches!(&app.program, Ok(program) if program.exec.target_kind().is_some())
+            {
+                items.push(app.open_targets_item(&self.launcher, rest));
             }
+            return Ok(List::new(items));
         }
 
-        // for something like `firefox %f`, should become `firefox` not `firefox ''`.
-        if !parsed_arg.is_empty() {
-            parsed_exec.push(parsed_arg);
+        // windows can only be moved with a window backend
+        let (window_query, workspace) = match &self.window_backend {
+            Some(_) => split_workspace(&query),
+            None => (query.as_str(), None),
+        };
+
+        // still show desktop entries if windows can't be listed, e.g. if
+        // the window tool isn't installed.
+        let windows: Vec<_> = match &self.window_backend {
+            Some(backend) => backend
+                .windows()
+                .await
+                .unwrap_or_else(|e| {
+                    eprintln!("failed to list windows: {e:#}");
+                    Vec::new()
+                })
+                .into_iter()
+                // hidden apps still name their windows
+                .map(|window| window_item(&listed, backend, window, workspace))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        if workspace.is_some() {
+            // only windows can be moved
+            return Ok(List::new(
+                rank::rank(window_query, &windows, rank::Weights::with_history()).await,
+            ));
         }
+
+        let windows = rank::rank(&query, &windows, rank::Weights::with_history()).await;
+        let (pinned, unpinned): (Vec<_>, Vec<_>) = shown
+            .into_iter()
+            .partition(|listed| self.pins.is_pinned(listed.id));
+        let pinned = search::rank(
+            &query,
+            pinned.into_iter().map(search_entry),
+            self.search_weights,
+        );
+        let entries = unpinned.into_iter().map(search_entry);
+
+        if query.is_empty() {
+            let (recent, rest) =
+                search::split_recent(entries, self.search_weights, self.recent_apps);
+            let sections = [
+                ("Pinned", pinned),
+                ("Running", windows),
+                ("Recent", recent),
+                ("Apps", rest),
+            ]
+            .into_iter()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(title, items)| ListSection::new(title, items))
+            .collect();
+            return Ok(List::from_sections(sections));
+        }
+
+        // open windows go above the entries that launch them, unless the
+        // entries are pinned
+        let mut items = pinned;
+        items.extend(windows);
+        items.extend(search::rank(&query, entries, self.search_weights));
+        Ok(List::new(items))
     }
+}
+
+/// Query prefix for listing hidden apps, so that they can be unhidden.
+const HIDDEN_QUERY: &str = "hidden:";
 
-    Ok(parsed_exec)
+/// Adds commands for pinning and hiding the app to it's item.
+///
+/// Each command sets the same query again to show the change.
+fn with_pin_commands<'a>(pins: &Pins, mut listed: Listed<'a>, query: &str) -> Listed<'a> {
+    let id = listed.id;
+    listed.item = if pins.is_hidden(id) {
+        listed
+            .item
+            .on_unhide_app(clone_async!(pins, id, query, |menu| {
+                pins.unhide(&id).await?;
+                menu.set_input(Input::new(query));
+                Ok(())
+            }))
+    } else {
+        listed
+            .item
+            .on_toggle_pin(clone_async!(pins, id, query, |menu| {
+                pins.toggle_pin(&id).await?;
+                menu.set_input(Input::new(query));
+                Ok(())
+            }))
+            .on_hide_app(clone_async!(pins, id, query, |menu| {
+                pins.hide(&id).await?;
+                menu.set_input(Input::new(query));
+                Ok(())
+            }))
+    };
+    listed
 }
 
-const USELESS_CATEGORIES: [&str; 7] = [
-    "System",
-    "Development",
-    "Qt",
-    "KDE",
-    "GNOME",
-    "GTK",
-    "Application",
-];
+fn search_entry<'b>(listed: &'b Listed<'_>) -> (&'b ListItem, &'b SearchTerms) {
+    (&listed.item, &listed.app.search_terms)
+}
+
+/// Finds the app whose name was completed at the start of the query,
+/// returning the rest of the query after it.
+fn completed_app<'a, 'b, 'q>(
+    listed: &[&'b Listed<'a>],
+    query: &'q str,
+) -> Option<(&'b Listed<'a>, &'q str)> {
+    listed
+        .iter()
+        .copied()
+        .filter_map(|listed| {
+            let name = &listed.app.name;
+            let prefix = query.get(..name.len())?;
+            let rest = query[name.len()..].strip_prefix(':')?;
+            prefix
+                .eq_ignore_ascii_case(name)
+                .then_some((listed, rest.trim_start()))
+        })
+        // prefer "Firefox Developer Edition:" over "Firefox:"
+        .max_by_key(|(listed, _)| listed.app.name.len())
+}
+
+/// Splits a query like `firefox > 3` into the search for a window and the
+/// workspace to move it to.
+///
+/// The `>` needs spaces around it, so that it can still be searched for.
+fn split_workspace(query: &str) -> (&str, Option<&str>) {
+    match query.rsplit_once(" > ") {
+        Some((search, workspace)) if !workspace.trim().is_empty() => {
+            (search.trim_end(), Some(workspace.trim()))
+        }
+        _ => (query, None),
+    }
+}
 
-static KDOTOOL_PATH: LazyLock<String> = LazyLock::new(|| {
-    let mut s = std::env::var("HOME").expect("HOME variable must be set");
-    s.push_str("/.cargo/bin/kdotool");
-    s
-});
+/// An item for an open window.
+///
+/// If a `workspace` was typed, activating the item moves the window there.
+fn window_item(
+    listed: &[Listed],
+    backend: &WindowBackend,
+    window: Window,
+    workspace: Option<&str>,
+) -> ListItem {
+    let class = window.class.to_lowercase();
+    let app = listed
+        .iter()
+        .map(|listed| listed.app)
+        .find(|app| app.class == class);
+
+    let mut description = app.map_or(window.class.clone(), |app| app.name.clone());
+    if let Some(workspace) = &window.workspace {
+        description.push_str(&format!(" · workspace {workspace}"));
+    }
+    if let Some(workspace) = workspace {
+        description.push_str(&format!(" → {workspace}"));
+    }
+    let workspace = workspace.map(str::to_string);
+    let title = if window.title.is_empty() {
+        window.class
+    } else {
+        window.title
+    };
+
+    ListItem::new(title)
+        .with_description(description)
+        .with_icon(app.and_then(|app| app.icon.clone()).map(Icon::Name))
+        .on_activate(clone_async!(backend, id = window.id, workspace, |menu| {
+            menu.close();
+            match &workspace {
+                Some(workspace) => backend.move_to_workspace(&id, Some(workspace)).await,
+                None => backend.activate(&id).await,
+            }
+        }))
+        .on_close_window(clone_async!(backend, id = window.id, |menu| {
+            menu.close();
+            backend.close(&id).await
+        }))
+        .on_minimize_window(clone_async!(backend, id = window.id, |menu| {
+            menu.close();
+            backend.minimize(&id).await
+        }))
+        .on_fullscreen_window(clone_async!(backend, id = window.id, |menu| {
+            menu.close();
+            backend.toggle_fullscreen(&id).await
+        }))
+        .on_move_window(clone_async!(backend, id = window.id, workspace, |menu| {
+            menu.close();
+            backend.move_to_workspace(&id, workspace.as_deref()).await
+        }))
+}
 
 fn main() {
     covey_plugin::run_server::<AppSwitcher>(env!("CARGO_PKG_NAME"))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    /// The defaults from the manifest.
+    fn config() -> Config {
+        Config {
+            window_backend: window_backend::WindowBackendSelection::None,
+            include_ids: String::new(),
+            exclude_ids: String::new(),
+            include_categories: String::new(),
+            exclude_categories: "System;Development;Qt;KDE;GNOME;GTK;Application".to_string(),
+            show_no_display: false,
+            require_icon: true,
+            show_hidden: false,
+            check_try_exec: true,
+            check_show_in: true,
+            launch_method: launch_method::LaunchMethodSelection::Direct,
+            launch_wrapper: String::new(),
+            terminal: String::new(),
+            recent_apps: 5,
+            generic_name_weight: 70,
+            keywords_weight: 70,
+            executable_weight: 60,
+            id_weight: 40,
+        }
+    }
+
+    /// Loads the fixture data dirs the same way the plugin loads the real
+    /// ones, with `home` as the user's data dir.
+    fn load(locale: &str) -> Apps {
+        let dirs = ["home", "share"].map(|dir| fixtures().join(dir).join("applications"));
+        let loader = Loader::new(&config(), dirs, vec![locale.to_string()], None).unwrap();
+        loader.load_dirs(loader.dirs.clone())
+    }
+
+    fn file<'a>(apps: &'a Apps, file_name: &str) -> &'a DesktopFile {
+        apps.iter()
+            .find(|(path, _)| path.ends_with(file_name))
+            .map(|(_, file)| file)
+            .unwrap_or_else(|| panic!("{file_name} wasn't loaded"))
+    }
+
+    fn app<'a>(apps: &'a Apps, file_name: &str) -> &'a App {
+        file(apps, file_name)
+            .app
+            .as_ref()
+            .unwrap_or_else(|| panic!("{file_name} was filtered out"))
+    }
+
+    fn argv(apps: &Apps, file_name: &str, targets: &[&str]) -> Vec<Vec<String>> {
+        let targets: Vec<_> = targets.iter().map(|target| target.to_string()).collect();
+        app(apps, file_name)
+            .program
+            .as_ref()
+            .unwrap()
+            .exec
+            .commands(&targets)
+            .unwrap()
+    }
+
+    #[test]
+    fn listed_items() {
+        let apps = load("en");
+        let mut items: Vec<_> = dedupe::dedupe(&apps)
+            .into_iter()
+            .map(|listed| (listed.item.title, listed.item.description))
+            .collect();
+        items.sort();
+
+        // the calculator is hidden by a file in the user's data dir
+        let expected = [
+            ("Browser", "Browse the web"),
+            ("Field Codes", ""),
+            (
+                "GNU Image Manipulation Program",
+                "Create images and edit photographs",
+            ),
+            ("Image Viewer", "Browse and view images"),
+            ("Missing Exec", ""),
+            ("Quoted Args", "Has arguments with spaces"),
+            ("Text Editor", "Edit text files"),
+            ("Unknown Field Code", ""),
+        ];
+        assert_eq!(
+            items,
+            expected.map(|(title, description)| (title.to_string(), description.to_string()))
+        );
+        assert!(
+            file(&apps, "home/applications/calculator.desktop")
+                .app
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn localized_names() {
+        let apps = load("de");
+        let editor = app(&apps, "text-editor.desktop");
+        assert_eq!(editor.name, "Texteditor");
+        assert_eq!(editor.item.title, "Texteditor");
+        assert_eq!(editor.item.description, "Textdateien bearbeiten");
+        // %c is the translated name
+        assert_eq!(
+            argv(&apps, "text-editor.desktop", &[]),
+            [["text-editor", "--title=Texteditor"]]
+        );
+
+        let apps = load("en");
+        assert_eq!(app(&apps, "text-editor.desktop").name, "Text Editor");
+        assert_eq!(
+            argv(&apps, "text-editor.desktop", &["https://example.com"]),
+            [["text-editor", "--title=Text Editor", "https://example.com"]]
+        );
+    }
+
+    #[test]
+    fn quoted_args() {
+        let apps = load("en");
+        assert_eq!(
+            argv(
+                &apps,
+                "quoted-args.desktop",
+                &["/tmp/a b.txt", "/tmp/c.txt"]
+            ),
+            [[
+                "/opt/quoted app/bin/quoted",
+                "--title",
+                "two words",
+                "--empty",
+                "",
+                "/tmp/a b.txt",
+                "/tmp/c.txt",
+            ]]
+        );
+        assert_eq!(
+            app(&apps, "quoted-args.desktop")
+                .program
+                .as_ref()
+                .unwrap()
+                .exec
+                .program_name(),
+            Some("quoted")
+        );
+    }
+
+    #[test]
+    fn field_codes() {
+        let apps = load("en");
+        let path = fixtures()
+            .join("share/applications/field-codes.desktop")
+            .canonicalize()
+            .unwrap();
+        let path = path.to_str().unwrap();
+
+        // %i is two arguments, and deprecated field codes are removed
+        assert_eq!(
+            argv(&apps, "field-codes.desktop", &[]),
+            [[
+                "field-codes",
+                "--progress=100%",
+                "--name=Field Codes",
+                "--desktop-file",
+                path,
+                "--icon",
+                "field-codes",
+            ]]
+        );
+        assert_eq!(
+            argv(
+                &apps,
+                "field-codes.desktop",
+                &["mailto:a@example.com", "/tmp"]
+            ),
+            [
+                [
+                    "field-codes",
+                    "--progress=100%",
+                    "--name=Field Codes",
+                    "--desktop-file",
+                    path,
+                    "--icon",
+                    "field-codes",
+                    "--open=mailto:a@example.com",
+                ],
+                [
+                    "field-codes",
+                    "--progress=100%",
+                    "--name=Field Codes",
+                    "--desktop-file",
+                    path,
+                    "--icon",
+                    "field-codes",
+                    "--open=/tmp",
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn workspaces_to_move_to() {
+        assert_eq!(split_workspace("firefox > 3"), ("firefox", Some("3")));
+        assert_eq!(split_workspace("a>b > 2: web"), ("a>b", Some("2: web")));
+        assert_eq!(split_workspace("firefox >"), ("firefox >", None));
+        assert_eq!(split_workspace("firefox >  "), ("firefox >  ", None));
+        assert_eq!(split_workspace("firefox"), ("firefox", None));
+
+        // `>` in an ordinary search
+        assert_eq!(split_workspace("a>b"), ("a>b", None));
+        assert_eq!(split_workspace("->"), ("->", None));
+        assert_eq!(split_workspace("firefox >3"), ("firefox >3", None));
+    }
+
+    #[test]
+    fn invalid_exec() {
+        let apps = load("en");
+
+        // still listed, but fail when launched
+        let unknown = app(&apps, "unknown-field-code.desktop");
+        let error = unknown.program.as_ref().unwrap_err();
+        assert!(error.contains("unknown field code %z"), "{error}");
+
+        let missing = app(&apps, "missing-exec.desktop");
+        let error = missing.program.as_ref().unwrap_err();
+        assert!(error.contains("missing Exec key"), "{error}");
+    }
+}
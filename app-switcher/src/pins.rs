@@ -0,0 +1,170 @@
+//! Apps that are pinned to the top of the list or hidden from it.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use covey_plugin::{Result, anyhow::Context};
+use serde::{Deserialize, Serialize};
+
+/// Desktop file ids of pinned and hidden apps, saved to a file whenever they
+/// change.
+#[derive(Debug, Clone)]
+pub struct Pins {
+    path: PathBuf,
+    ids: Arc<RwLock<PinnedIds>>,
+    /// Held while saving, so that saves don't overlap.
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PinnedIds {
+    pinned: BTreeSet<String>,
+    hidden: BTreeSet<String>,
+}
+
+impl Pins {
+    /// Reads the pins from `path`, starting with none if it doesn't exist
+    /// yet.
+    ///
+    /// If the file can't be read, nothing is pinned or hidden and the file
+    /// is moved to `pins.json.bak` so that it isn't overwritten.
+    pub async fn load(path: PathBuf) -> Self {
+        let ids = match read_ids(&path).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                let backup_path = with_suffix(&path, ".bak");
+                match tokio::fs::rename(&path, &backup_path).await {
+                    Ok(()) => eprintln!("{e:#}, moved it to {}", backup_path.display()),
+                    Err(rename_error) => {
+                        eprintln!("{e:#}, and failed to move it out of the way: {rename_error}");
+                    }
+                }
+                PinnedIds::default()
+            }
+        };
+
+        Self {
+            path,
+            ids: Arc::new(RwLock::new(ids)),
+            saving: Arc::default(),
+        }
+    }
+
+    pub fn is_pinned(&self, id: &str) -> bool {
+        self.ids.read().unwrap().pinned.contains(id)
+    }
+
+    pub fn is_hidden(&self, id: &str) -> bool {
+        self.ids.read().unwrap().hidden.contains(id)
+    }
+
+    pub async fn toggle_pin(&self, id: &str) -> Result<()> {
+        self.update(|ids| {
+            if !ids.pinned.remove(id) {
+                ids.pinned.insert(id.to_string());
+            }
+        })
+        .await
+    }
+
+    /// Hides an app, which also unpins it.
+    pub async fn hide(&self, id: &str) -> Result<()> {
+        self.update(|ids| {
+            ids.pinned.remove(id);
+            ids.hidden.insert(id.to_string());
+        })
+        .await
+    }
+
+    pub async fn unhide(&self, id: &str) -> Result<()> {
+        self.update(|ids| {
+            ids.hidden.remove(id);
+        })
+        .await
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut PinnedIds)) -> Result<()> {
+        f(&mut self.ids.write().unwrap());
+        self.save().await.context("failed to save pins")
+    }
+
+    /// Writes the pins to a temporary file and then replaces the pins file
+    /// with it, so that the pins file is never partly written.
+    async fn save(&self) -> Result<()> {
+        let _saving = self.saving.lock().await;
+        // read after waiting for other saves, so the newest pins are always
+        // saved last.
+        let json = serde_json::to_vec(&*self.ids.read().unwrap())?;
+
+        let temp_path = with_suffix(&self.path, ".tmp");
+        tokio::fs::write(&temp_path, json).await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+        Ok(())
+    }
+}
+
+async fn read_ids(path: &Path) -> Result<PinnedIds> {
+    match tokio::fs::read(path).await {
+        Ok(json) => serde_json::from_slice(&json)
+            .with_context(|| format!("invalid pins file {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(PinnedIds::default()),
+        Err(e) => Err(e).context("failed to read pins file"),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn saved_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins.json");
+
+        // desktop ids don't have the .desktop extension
+        let pins = Pins::load(path.clone()).await;
+        pins.toggle_pin("firefox").await.unwrap();
+        pins.toggle_pin("org.gimp.GIMP").await.unwrap();
+        pins.toggle_pin("org.gimp.GIMP").await.unwrap();
+        pins.toggle_pin("avahi-discover").await.unwrap();
+        pins.hide("avahi-discover").await.unwrap();
+        pins.hide("bssh").await.unwrap();
+        pins.unhide("bssh").await.unwrap();
+
+        let pins = Pins::load(path).await;
+        assert!(pins.is_pinned("firefox"));
+        assert!(!pins.is_pinned("org.gimp.GIMP"));
+        assert!(!pins.is_pinned("avahi-discover"));
+        assert!(pins.is_hidden("avahi-discover"));
+        assert!(!pins.is_hidden("bssh"));
+        assert!(!pins.is_hidden("firefox"));
+        assert!(!dir.path().join("pins.json.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn sets_aside_unreadable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins.json");
+        std::fs::write(&path, "firefox").unwrap();
+
+        let pins = Pins::load(path.clone()).await;
+        assert!(!pins.is_pinned("firefox"));
+        pins.toggle_pin("firefox").await.unwrap();
+
+        let backup = std::fs::read_to_string(dir.path().join("pins.json.bak")).unwrap();
+        assert_eq!(backup, "firefox");
+        assert!(Pins::load(path).await.is_pinned("firefox"));
+    }
+}
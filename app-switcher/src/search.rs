@@ -0,0 +1,181 @@
+//! Searching apps by desktop entry keys that aren't shown in the list.
+
+use std::time::SystemTime;
+
+use covey_plugin::{
+    ListItem,
+    rank::{self, Weights},
+};
+use freedesktop_desktop_entry::DesktopEntry;
+use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
+
+use crate::{Config, exec::Exec};
+
+/// Extra text that an app can be found by.
+#[derive(Debug, Clone)]
+pub struct SearchTerms {
+    generic_name: Option<String>,
+    keywords: Vec<String>,
+    executable: Option<String>,
+    id: String,
+}
+
+/// How much each of the [`SearchTerms`] counts, relative to the title.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchWeights {
+    generic_name: f32,
+    keywords: f32,
+    executable: f32,
+    id: f32,
+}
+
+impl SearchTerms {
+    pub fn new(entry: &DesktopEntry, exec: Option<&Exec>, locales: &[impl AsRef<str>]) -> Self {
+        Self {
+            generic_name: entry.generic_name(locales).map(|name| name.into_owned()),
+            keywords: entry
+                .keywords(locales)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|keyword| !keyword.is_empty())
+                .map(|keyword| keyword.into_owned())
+                .collect(),
+            executable: exec.and_then(Exec::program_name).map(str::to_string),
+            id: entry.id().to_string(),
+        }
+    }
+
+    fn accuracy(&self, matcher: &SkimMatcherV2, query: &str, weights: SearchWeights) -> f32 {
+        let score = |text: &str, weight: f32| {
+            let score = matcher.fuzzy_match(text, query).unwrap_or(0) as f32;
+            score * weight
+        };
+
+        let keywords = self
+            .keywords
+            .iter()
+            .map(|keyword| score(keyword, weights.keywords))
+            .fold(0.0, f32::max);
+
+        self.generic_name
+            .as_deref()
+            .map_or(0.0, |name| score(name, weights.generic_name))
+            + keywords
+            + self
+                .executable
+                .as_deref()
+                .map_or(0.0, |exe| score(exe, weights.executable))
+            + score(&self.id, weights.id)
+    }
+}
+
+impl SearchWeights {
+    pub fn from_config(config: &Config) -> Self {
+        let percent = |weight: i32| weight as f32 / 100.0;
+        Self {
+            generic_name: percent(config.generic_name_weight),
+            keywords: percent(config.keywords_weight),
+            executable: percent(config.executable_weight),
+            id: percent(config.id_weight),
+        }
+    }
+}
+
+/// Like [`rank::rank`], but also matches the query against the search terms
+/// of each item.
+pub fn rank<'a>(
+    query: &str,
+    items: impl IntoIterator<Item = (&'a ListItem, &'a SearchTerms)>,
+    search_weights: SearchWeights,
+) -> Vec<ListItem> {
+    Scorer::new(search_weights)
+        .sort(query, items)
+        .into_iter()
+        .map(|(item, _)| item.clone())
+        .collect()
+}
+
+/// Ranks items for an empty query, splitting off up to `limit` items that
+/// were launched recently or often.
+///
+/// Returns the recent items and then the rest, both in ranked order.
+pub fn split_recent<'a>(
+    items: impl IntoIterator<Item = (&'a ListItem, &'a SearchTerms)>,
+    search_weights: SearchWeights,
+    limit: usize,
+) -> (Vec<ListItem>, Vec<ListItem>) {
+    let scorer = Scorer::new(search_weights);
+    // nothing has this title, so it's the score of an item that was never
+    // launched.
+    let never_launched = scorer.history_score(&ListItem::new(""));
+
+    let sorted = scorer.sort("", items);
+    let recent_count = sorted
+        .iter()
+        .take(limit)
+        .take_while(|(_, score)| *score > never_launched)
+        .count();
+
+    let mut items = sorted.into_iter().map(|(item, _)| item.clone());
+    let recent = items.by_ref().take(recent_count).collect();
+    (recent, items.collect())
+}
+
+/// Everything needed to score items, loaded once per query.
+struct Scorer {
+    weights: Weights,
+    search_weights: SearchWeights,
+    visits: rank::Visits,
+    now: SystemTime,
+    matcher: SkimMatcherV2,
+}
+
+impl Scorer {
+    fn new(search_weights: SearchWeights) -> Self {
+        Self {
+            weights: Weights::with_history(),
+            search_weights,
+            visits: rank::Visits::from_file(),
+            now: SystemTime::now(),
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// Scores and sorts the items that match the query.
+    fn sort<'a>(
+        &self,
+        query: &str,
+        items: impl IntoIterator<Item = (&'a ListItem, &'a SearchTerms)>,
+    ) -> Vec<(&'a ListItem, f32)> {
+        let mut scored: Vec<_> = items
+            .into_iter()
+            .map(|(item, terms)| {
+                let mut accuracy = item.accuracy(query, self.weights);
+                if !query.is_empty() {
+                    accuracy += terms.accuracy(&self.matcher, query, self.search_weights);
+                }
+                (item, self.combine(item, accuracy))
+            })
+            .filter(|(_, score)| query.is_empty() || *score > 1.0)
+            .collect();
+
+        // By reverse score, then alphabetically
+        scored.sort_unstable_by(|(item1, score1), (item2, score2)| {
+            score2
+                .total_cmp(score1)
+                .then_with(|| item1.title.cmp(&item2.title))
+        });
+        scored
+    }
+
+    /// The score of an item for an empty query, which only depends on how it
+    /// was used before.
+    fn history_score(&self, item: &ListItem) -> f32 {
+        self.combine(item, item.accuracy("", self.weights))
+    }
+
+    fn combine(&self, item: &ListItem, accuracy: f32) -> f32 {
+        item.frecency(&self.visits, self.now, self.weights)
+            .combine_with_accuracy(accuracy)
+    }
+}
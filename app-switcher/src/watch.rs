@@ -0,0 +1,186 @@
+//! Watching desktop file directories for changes.
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use covey_plugin::Result;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+/// How long to wait for more changes before reporting them. Installing or
+/// upgrading packages changes lots of files in quick succession, which should
+/// only cause a single reload.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches desktop file directories until it's dropped.
+pub struct DirWatcher {
+    _watcher: Arc<Mutex<RecommendedWatcher>>,
+}
+
+/// Starts watching every directory in `dirs`, recursively. Directories that
+/// don't exist yet, or are removed later, are watched once they're created.
+///
+/// The changes are sent to [`Changes`] until the returned watcher is dropped.
+pub fn watch(dirs: impl IntoIterator<Item = PathBuf>) -> Result<(DirWatcher, Changes)> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) => _ = sender.send(event.paths),
+            Err(e) => eprintln!("error watching desktop files: {e}"),
+        })?;
+
+    let mut changes = Changes {
+        receiver,
+        watcher: Weak::new(),
+        watched: Vec::new(),
+        missing: dirs.into_iter().collect(),
+        parents: BTreeSet::new(),
+    };
+    changes.update_missing(&mut watcher);
+
+    let watcher = Arc::new(Mutex::new(watcher));
+    changes.watcher = Arc::downgrade(&watcher);
+    Ok((DirWatcher { _watcher: watcher }, changes))
+}
+
+/// Receives changed paths from a [`watch`]er.
+pub struct Changes {
+    receiver: mpsc::UnboundedReceiver<Vec<PathBuf>>,
+    /// Used to watch the missing directories when they're created.
+    watcher: Weak<Mutex<RecommendedWatcher>>,
+    /// Canonical paths of the directories that are watched recursively.
+    watched: Vec<PathBuf>,
+    /// Directories that don't exist yet, or were removed.
+    missing: Vec<PathBuf>,
+    /// The nearest existing parents of the missing directories, which are
+    /// watched to find out when the directories are created.
+    parents: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    /// Waits until something changes and then settles down, returning every
+    /// file or directory that was created, modified or removed.
+    ///
+    /// Returns `None` once the watcher is dropped.
+    pub async fn next(&mut self) -> Option<BTreeSet<PathBuf>> {
+        loop {
+            let mut changed = BTreeSet::from_iter(self.receiver.recv().await?);
+
+            while let Ok(Some(paths)) = tokio::time::timeout(DEBOUNCE, self.receiver.recv()).await {
+                changed.extend(paths);
+            }
+
+            // a removed directory's watch is gone, so it has to be watched
+            // again when it's created again. A package manager could also
+            // have replaced it already.
+            let (replaced, watched): (Vec<_>, Vec<_>) = self
+                .watched
+                .drain(..)
+                .partition(|dir| changed.contains(dir) || !dir.exists());
+            self.watched = watched;
+            self.missing.extend(replaced.iter().cloned());
+
+            if !self.missing.is_empty() {
+                let watcher = self.watcher.upgrade()?;
+                let mut watcher = watcher.lock().ok()?;
+                for dir in &replaced {
+                    _ = watcher.unwatch(dir);
+                }
+                changed.extend(self.update_missing(&mut watcher));
+            }
+
+            // other changes in the parents of missing directories aren't
+            // desktop files
+            changed.retain(|path| {
+                self.watched
+                    .iter()
+                    .chain(&replaced)
+                    .any(|dir| path.starts_with(dir))
+            });
+            if !changed.is_empty() {
+                return Some(changed);
+            }
+        }
+    }
+
+    /// Starts watching the missing directories that exist now, and watches
+    /// the nearest parents of the rest. Returns the directories that were
+    /// created.
+    fn update_missing(&mut self, watcher: &mut RecommendedWatcher) -> Vec<PathBuf> {
+        let mut created = Vec::new();
+
+        // something could be created before its parent is watched, so this
+        // repeats until the parents stay the same after watching them
+        loop {
+            self.missing.retain(|dir| {
+                // events are reported with paths inside the watched directory,
+                // so make them match the canonical paths of the desktop files.
+                let Ok(dir) = dir.canonicalize() else {
+                    return true;
+                };
+                match watcher.watch(&dir, RecursiveMode::Recursive) {
+                    Ok(()) => {
+                        self.watched.push(dir.clone());
+                        created.push(dir);
+                    }
+                    Err(e) => eprintln!("failed to watch {}: {e}", dir.display()),
+                }
+                false
+            });
+
+            let parents: BTreeSet<_> = self
+                .missing
+                .iter()
+                .filter_map(|dir| dir.ancestors().skip(1).find(|parent| parent.is_dir()))
+                .map(PathBuf::from)
+                .collect();
+            if parents == self.parents {
+                return created;
+            }
+
+            for parent in self.parents.difference(&parents) {
+                _ = watcher.unwatch(parent);
+            }
+            for parent in parents.difference(&self.parents) {
+                if let Err(e) = watcher.watch(parent, RecursiveMode::NonRecursive) {
+                    eprintln!("failed to watch {}: {e}", parent.display());
+                }
+            }
+            self.parents = parents;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn directories_created_later() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("share/applications");
+        let (_watcher, mut changes) = watch([dir.clone()]).unwrap();
+
+        // unrelated files next to the missing directory aren't reported
+        std::fs::write(temp_dir.path().join("notes.txt"), "").unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(changes.next().await, Some(BTreeSet::from([dir.clone()])));
+
+        let file = dir.join("app.desktop");
+        std::fs::write(&file, "").unwrap();
+        assert!(changes.next().await.unwrap().contains(&file));
+
+        // removed and created again, like by a package manager
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(changes.next().await.unwrap().contains(&dir));
+        std::fs::create_dir(&dir).unwrap();
+        assert_eq!(changes.next().await, Some(BTreeSet::from([dir.clone()])));
+        std::fs::write(&file, "").unwrap();
+        assert!(changes.next().await.unwrap().contains(&file));
+    }
+}
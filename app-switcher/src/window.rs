@@ -0,0 +1,411 @@
+//! Listing and controlling open windows through an external window tool.
+
+mod hyprland;
+mod kdotool;
+mod sway;
+mod x11;
+
+use std::{env, ffi::OsStr, path::Path, process::Stdio};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, bail},
+};
+pub use hyprland::Hyprland;
+pub use kdotool::Kdotool;
+pub use sway::Sway;
+use tokio::process::Command;
+pub use x11::X11;
+
+use crate::window_backend::WindowBackendSelection;
+
+/// A window that is currently open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Identifier that the window tool uses to refer to this window.
+    pub id: String,
+    pub title: String,
+    /// The WM class (X11) or app id (Wayland) of the window.
+    pub class: String,
+    /// The workspace (virtual desktop) the window is on, if known.
+    pub workspace: Option<String>,
+}
+
+/// A window manager specific tool to list and control windows with.
+#[derive(Debug, Clone)]
+pub enum WindowBackend {
+    Kdotool(Kdotool),
+    /// Also used for i3, which has the same IPC.
+    Sway(Sway),
+    Hyprland(Hyprland),
+    X11(X11),
+}
+
+impl WindowBackend {
+    /// Chooses the backend from the `window-backend` config option.
+    ///
+    /// Returns `None` if windows should not be listed at all.
+    pub fn from_config(selection: &WindowBackendSelection) -> Option<Self> {
+        match selection {
+            WindowBackendSelection::Auto => Self::detect(),
+            WindowBackendSelection::None => None,
+            WindowBackendSelection::Kdotool => Some(Self::Kdotool(Kdotool::default())),
+            WindowBackendSelection::Sway => Some(Self::Sway(Sway::new("swaymsg"))),
+            WindowBackendSelection::I3 => Some(Self::Sway(Sway::new("i3-msg"))),
+            WindowBackendSelection::Hyprland => Some(Self::Hyprland(Hyprland::new("hyprctl"))),
+            WindowBackendSelection::X11 => Some(Self::X11(X11::new("wmctrl", "xdotool"))),
+        }
+    }
+
+    /// Guesses the backend from the environment of the current session.
+    pub fn detect() -> Option<Self> {
+        let desktops = current_desktops();
+        let is_desktop = |name: &str| desktops.iter().any(|d| d.eq_ignore_ascii_case(name));
+
+        if is_desktop("KDE") {
+            Some(Self::Kdotool(Kdotool::default()))
+        } else if is_desktop("Hyprland") || env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(Self::Hyprland(Hyprland::new("hyprctl")))
+        } else if is_desktop("sway") || env::var_os("SWAYSOCK").is_some() {
+            Some(Self::Sway(Sway::new("swaymsg")))
+        } else if is_desktop("i3") || env::var_os("I3SOCK").is_some() {
+            Some(Self::Sway(Sway::new("i3-msg")))
+        } else if env::var_os("WAYLAND_DISPLAY").is_none() && env::var_os("DISPLAY").is_some() {
+            Some(Self::X11(X11::new("wmctrl", "xdotool")))
+        } else {
+            None
+        }
+    }
+
+    /// Lists every open window.
+    pub async fn windows(&self) -> Result<Vec<Window>> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.windows().await,
+            Self::Sway(sway) => sway.windows().await,
+            Self::Hyprland(hyprland) => hyprland.windows().await,
+            Self::X11(x11) => x11.windows().await,
+        }
+    }
+
+    /// Focuses a specific window.
+    pub async fn activate(&self, window_id: &str) -> Result<()> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.activate(window_id).await,
+            Self::Sway(sway) => sway.activate(window_id).await,
+            Self::Hyprland(hyprland) => hyprland.activate(window_id).await,
+            Self::X11(x11) => x11.activate(window_id).await,
+        }
+    }
+
+    pub async fn close(&self, window_id: &str) -> Result<()> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.close(window_id).await,
+            Self::Sway(sway) => sway.close(window_id).await,
+            Self::Hyprland(hyprland) => hyprland.close(window_id).await,
+            Self::X11(x11) => x11.close(window_id).await,
+        }
+    }
+
+    /// Minimizes a window, or hides it somewhere similar if the window
+    /// manager doesn't minimize.
+    pub async fn minimize(&self, window_id: &str) -> Result<()> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.minimize(window_id).await,
+            Self::Sway(sway) => sway.minimize(window_id).await,
+            Self::Hyprland(hyprland) => hyprland.minimize(window_id).await,
+            Self::X11(x11) => x11.minimize(window_id).await,
+        }
+    }
+
+    pub async fn toggle_fullscreen(&self, window_id: &str) -> Result<()> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.toggle_fullscreen(window_id).await,
+            Self::Sway(sway) => sway.toggle_fullscreen(window_id).await,
+            Self::Hyprland(hyprland) => hyprland.toggle_fullscreen(window_id).await,
+            Self::X11(x11) => x11.toggle_fullscreen(window_id).await,
+        }
+    }
+
+    /// Moves a window to a workspace, or the one that is currently shown if
+    /// `workspace` is `None`, and focuses it.
+    ///
+    /// Workspaces are named like in [`Window::workspace`].
+    pub async fn move_to_workspace(&self, window_id: &str, workspace: Option<&str>) -> Result<()> {
+        match self {
+            Self::Kdotool(kdotool) => kdotool.move_to_workspace(window_id, workspace).await,
+            Self::Sway(sway) => sway.move_to_workspace(window_id, workspace).await,
+            Self::Hyprland(hyprland) => hyprland.move_to_workspace(window_id, workspace).await,
+            Self::X11(x11) => x11.move_to_workspace(window_id, workspace).await,
+        }?;
+        self.activate(window_id).await
+    }
+
+    /// Focuses the first window with a matching class, returning `Err` if
+    /// there are none.
+    pub async fn activate_class(&self, class: &str) -> Result<()> {
+        let window = self
+            .windows()
+            .await?
+            .into_iter()
+            .find(|window| window.class.eq_ignore_ascii_case(class))
+            .context("window not found")?;
+
+        self.activate(&window.id).await
+    }
+}
+
+/// The desktops listed in `XDG_CURRENT_DESKTOP`.
+pub fn current_desktops() -> Vec<String> {
+    env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn command<S: AsRef<OsStr>>(program: &Path, args: impl IntoIterator<Item = S>) -> Command {
+    let mut command = Command::new(program);
+    command.args(args).stdout(Stdio::piped());
+    command
+}
+
+/// Runs a program, returning its stdout if it exits successfully.
+async fn output<S: AsRef<OsStr>>(
+    program: &Path,
+    args: impl IntoIterator<Item = S>,
+) -> Result<String> {
+    let output = command(program, args).spawn()?.wait_with_output().await?;
+
+    if !output.status.success() {
+        bail!("{} failed: {:?}", program.display(), output.status)
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+
+    /// Writes an executable shell script to stand in for a window tool.
+    fn fake_executable(dir: &Path, name: &str, script: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn window(id: &str, title: &str, class: &str, workspace: Option<&str>) -> Window {
+        Window {
+            id: id.to_string(),
+            title: title.to_string(),
+            class: class.to_string(),
+            workspace: workspace.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn kdotool() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = WindowBackend::Kdotool(Kdotool::new(fake_executable(
+            dir.path(),
+            "kdotool",
+            r#"
+case "$1 $2" in
+    "search .") printf '{aaa}\n{bbb}\n{gone}\n' ;;
+    "getwindowname {aaa}") printf 'GitHub — Mozilla Firefox\nfirefox\n1\n' ;;
+    "getwindowname {bbb}") printf '~ : bash — Konsole\nkonsole\n2\n' ;;
+    "getwindowname {gone}") exit 1 ;;
+    "windowactivate {aaa}" | "windowactivate {bbb}") ;;
+    "windowclose {aaa}" | "windowminimize {aaa}") ;;
+    "windowstate --toggle") [ "$3 $4" = "FULLSCREEN {aaa}" ] ;;
+    "get_desktop ") echo 2 ;;
+    "set_desktop_for_window {aaa}") [ "$3" = 2 ] || [ "$3" = 3 ] ;;
+    *) exit 1 ;;
+esac
+"#,
+        )));
+
+        assert_eq!(
+            backend.windows().await.unwrap(),
+            [
+                window("{aaa}", "GitHub — Mozilla Firefox", "firefox", Some("1")),
+                window("{bbb}", "~ : bash — Konsole", "konsole", Some("2")),
+            ]
+        );
+        backend.activate("{aaa}").await.unwrap();
+        assert!(backend.activate("{gone}").await.is_err());
+        backend.activate_class("Konsole").await.unwrap();
+        assert!(backend.activate_class("dolphin").await.is_err());
+        backend.close("{aaa}").await.unwrap();
+        backend.minimize("{aaa}").await.unwrap();
+        backend.toggle_fullscreen("{aaa}").await.unwrap();
+        backend.move_to_workspace("{aaa}", None).await.unwrap();
+        backend.move_to_workspace("{aaa}", Some("3")).await.unwrap();
+        assert!(backend.move_to_workspace("{aaa}", Some("4")).await.is_err());
+        assert!(backend.close("{gone}").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn sway() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = WindowBackend::Sway(Sway::new(fake_executable(
+            dir.path(),
+            "swaymsg",
+            r#"
+case "$*" in
+    "-t get_tree -r") cat <<'EOF'
+{"id": 1, "type": "root", "name": "root", "nodes": [
+  {"id": 2, "type": "output", "name": "eDP-1", "nodes": [
+    {"id": 3, "type": "workspace", "name": "1", "nodes": [
+      {"id": 4, "type": "con", "name": null, "nodes": [
+        {"id": 5, "type": "con", "name": "vim", "app_id": "foot", "nodes": []},
+        {"id": 6, "type": "con", "name": "Steam", "app_id": null, "window": 123,
+         "window_properties": {"class": "steam"}, "nodes": []}
+      ]}
+    ], "floating_nodes": [
+      {"id": 7, "type": "floating_con", "name": "Calculator", "app_id": "qalculate-gtk",
+       "nodes": []}
+    ]}
+  ]}
+]}
+EOF
+    ;;
+    "-t get_workspaces") cat <<'EOF'
+[{"id": 3, "name": "1", "focused": false}, {"id": 8, "name": "2: web", "focused": true}]
+EOF
+    ;;
+    "[con_id=5] focus" | "[con_id=5] kill" | "[con_id=5] move scratchpad") ;;
+    "[con_id=5] fullscreen toggle") ;;
+    '[con_id=5] move container to workspace "2: web"') ;;
+    '[con_id=5] move container to workspace "3: mail"') ;;
+    *) exit 1 ;;
+esac
+"#,
+        )));
+
+        assert_eq!(
+            backend.windows().await.unwrap(),
+            [
+                window("5", "vim", "foot", Some("1")),
+                window("6", "Steam", "steam", Some("1")),
+                window("7", "Calculator", "qalculate-gtk", Some("1")),
+            ]
+        );
+        backend.activate("5").await.unwrap();
+        assert!(backend.activate("8").await.is_err());
+        backend.close("5").await.unwrap();
+        backend.minimize("5").await.unwrap();
+        backend.toggle_fullscreen("5").await.unwrap();
+        backend.move_to_workspace("5", None).await.unwrap();
+        backend
+            .move_to_workspace("5", Some("3: mail"))
+            .await
+            .unwrap();
+        assert!(backend.move_to_workspace("8", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hyprland() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = WindowBackend::Hyprland(Hyprland::new(fake_executable(
+            dir.path(),
+            "hyprctl",
+            r#"
+case "$*" in
+    "clients -j") cat <<'EOF'
+[
+  {"address": "0x1a", "mapped": true, "hidden": false, "title": "nvim",
+   "class": "kitty", "workspace": {"id": 2, "name": "2"}},
+  {"address": "0x2b", "mapped": false, "hidden": false, "title": "",
+   "class": "", "workspace": {"id": -1, "name": ""}}
+]
+EOF
+    ;;
+    "activeworkspace -j") echo '{"id": 4, "name": "4"}' ;;
+    "dispatch focuswindow address:0x1a" | "dispatch closewindow address:0x1a") echo ok ;;
+    "dispatch movetoworkspacesilent special:minimized,address:0x1a") echo ok ;;
+    "dispatch fullscreen 0" | "dispatch movetoworkspace 4,address:0x1a") echo ok ;;
+    "dispatch movetoworkspace 7,address:0x1a") echo ok ;;
+    "dispatch movetoworkspace name:web,address:0x1a") echo ok ;;
+    dispatch*) echo "No such window found" ;;
+    *) exit 1 ;;
+esac
+"#,
+        )));
+
+        assert_eq!(
+            backend.windows().await.unwrap(),
+            [window("0x1a", "nvim", "kitty", Some("2"))]
+        );
+        backend.activate("0x1a").await.unwrap();
+        assert!(backend.activate("0x2b").await.is_err());
+        backend.close("0x1a").await.unwrap();
+        backend.minimize("0x1a").await.unwrap();
+        backend.toggle_fullscreen("0x1a").await.unwrap();
+        backend.move_to_workspace("0x1a", None).await.unwrap();
+        backend.move_to_workspace("0x1a", Some("7")).await.unwrap();
+        backend
+            .move_to_workspace("0x1a", Some("web"))
+            .await
+            .unwrap();
+        assert!(backend.close("0x2b").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn x11() {
+        let dir = tempfile::tempdir().unwrap();
+        let xdotool = fake_executable(
+            dir.path(),
+            "xdotool",
+            r#"[ "$*" = "windowminimize 0x03a00003" ]"#,
+        );
+        let wmctrl = fake_executable(
+            dir.path(),
+            "wmctrl",
+            r#"
+case "$*" in
+    "-l -x") cat <<'EOF'
+0x03a00003  0 Navigator.firefox     laptop Mozilla Firefox
+0x04200007 -1 xfce4-panel.Xfce4-panel  laptop xfce4-panel
+0x05000004  1 org.kde.konsole.konsole  laptop ~  :  bash
+EOF
+    ;;
+    "-i -a 0x03a00003" | "-i -c 0x03a00003" | "-i -R 0x03a00003") ;;
+    "-i -r 0x03a00003 -b toggle,fullscreen" | "-i -r 0x03a00003 -t 2") ;;
+    *) exit 1 ;;
+esac
+"#,
+        );
+        let backend = WindowBackend::X11(X11::new(wmctrl, xdotool));
+
+        assert_eq!(
+            backend.windows().await.unwrap(),
+            [
+                window("0x03a00003", "Mozilla Firefox", "firefox", Some("1")),
+                window("0x04200007", "xfce4-panel", "Xfce4-panel", None),
+                window("0x05000004", "~  :  bash", "konsole", Some("2")),
+            ]
+        );
+        backend.activate("0x03a00003").await.unwrap();
+        assert!(backend.activate("0x1").await.is_err());
+        backend.close("0x03a00003").await.unwrap();
+        backend.minimize("0x03a00003").await.unwrap();
+        assert!(backend.minimize("0x1").await.is_err());
+        backend.toggle_fullscreen("0x03a00003").await.unwrap();
+        backend.move_to_workspace("0x03a00003", None).await.unwrap();
+        backend
+            .move_to_workspace("0x03a00003", Some("3"))
+            .await
+            .unwrap();
+        assert!(
+            backend
+                .move_to_workspace("0x03a00003", Some("0"))
+                .await
+                .is_err()
+        );
+    }
+}
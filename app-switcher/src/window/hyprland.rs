@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use covey_plugin::{Result, anyhow::bail};
+use serde::Deserialize;
+
+use super::Window;
+
+/// Controls windows with `hyprctl` on Hyprland.
+#[derive(Debug, Clone)]
+pub struct Hyprland {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Client {
+    address: String,
+    mapped: bool,
+    title: String,
+    class: String,
+    workspace: Workspace,
+}
+
+#[derive(Deserialize)]
+struct Workspace {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ActiveWorkspace {
+    id: i64,
+}
+
+impl Hyprland {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub async fn windows(&self) -> Result<Vec<Window>> {
+        let clients: Vec<Client> =
+            serde_json::from_str(&super::output(&self.path, ["clients", "-j"]).await?)?;
+
+        Ok(clients
+            .into_iter()
+            .filter(|client| client.mapped)
+            .map(|client| Window {
+                id: client.address,
+                title: client.title,
+                class: client.class,
+                workspace: Some(client.workspace.name).filter(|name| !name.is_empty()),
+            })
+            .collect())
+    }
+
+    pub async fn activate(&self, window_id: &str) -> Result<()> {
+        self.dispatch("focuswindow", &format!("address:{window_id}"))
+            .await
+    }
+
+    pub async fn close(&self, window_id: &str) -> Result<()> {
+        self.dispatch("closewindow", &format!("address:{window_id}"))
+            .await
+    }
+
+    /// Hyprland doesn't have minimizing, so this moves the window to a
+    /// special workspace instead.
+    pub async fn minimize(&self, window_id: &str) -> Result<()> {
+        self.dispatch(
+            "movetoworkspacesilent",
+            &format!("special:minimized,address:{window_id}"),
+        )
+        .await
+    }
+
+    /// Only the focused window can be made fullscreen, so this also focuses
+    /// the window.
+    pub async fn toggle_fullscreen(&self, window_id: &str) -> Result<()> {
+        self.activate(window_id).await?;
+        self.dispatch("fullscreen", "0").await
+    }
+
+    /// Moves a window to the workspace with an id, or a name otherwise,
+    /// which is created if it doesn't exist yet.
+    pub async fn move_to_workspace(&self, window_id: &str, workspace: Option<&str>) -> Result<()> {
+        let workspace = match workspace {
+            Some(id) if id.parse::<i64>().is_ok() => id.to_string(),
+            Some(name) => format!("name:{name}"),
+            None => {
+                let active: ActiveWorkspace = serde_json::from_str(
+                    &super::output(&self.path, ["activeworkspace", "-j"]).await?,
+                )?;
+                active.id.to_string()
+            }
+        };
+        self.dispatch(
+            "movetoworkspace",
+            &format!("{workspace},address:{window_id}"),
+        )
+        .await
+    }
+
+    /// Runs a `hyprctl dispatch` command.
+    async fn dispatch(&self, dispatcher: &str, arg: &str) -> Result<()> {
+        // hyprctl exits successfully even if the dispatcher fails, but only
+        // prints "ok" if it succeeds.
+        let output = super::output(&self.path, ["dispatch", dispatcher, arg]).await?;
+        if output.trim() != "ok" {
+            bail!("hyprctl dispatch failed: {}", output.trim())
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use covey_plugin::Result;
+
+use super::Window;
+
+/// Controls windows with [kdotool](https://github.com/jinliu/kdotool) on KDE
+/// Plasma.
+#[derive(Debug, Clone)]
+pub struct Kdotool {
+    path: PathBuf,
+}
+
+impl Default for Kdotool {
+    /// Uses kdotool from `PATH`, or from where `cargo install` puts it if it
+    /// isn't in `PATH`.
+    fn default() -> Self {
+        if which_in_path("kdotool") || !Path::new(&*CARGO_KDOTOOL_PATH).exists() {
+            Self::new("kdotool")
+        } else {
+            Self::new(&*CARGO_KDOTOOL_PATH)
+        }
+    }
+}
+
+impl Kdotool {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub async fn windows(&self) -> Result<Vec<Window>> {
+        let ids = super::output(&self.path, ["search", "."]).await?;
+
+        // spawn everything first so that they all run concurrently.
+        let children = ids
+            .lines()
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let child = super::command(
+                    &self.path,
+                    [
+                        "getwindowname",
+                        id,
+                        "getwindowclassname",
+                        id,
+                        "get_desktop_for_window",
+                        id,
+                    ],
+                )
+                .spawn()?;
+                Ok((id, child))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut windows = Vec::with_capacity(children.len());
+        for (id, child) in children {
+            let output = child.wait_with_output().await?;
+            // the window may have closed since searching
+            if !output.status.success() {
+                continue;
+            }
+
+            let output = String::from_utf8(output.stdout)?;
+            let mut lines = output.lines();
+            let (Some(title), Some(class)) = (lines.next(), lines.next()) else {
+                continue;
+            };
+            windows.push(Window {
+                id: id.to_string(),
+                title: title.to_string(),
+                class: class.to_string(),
+                workspace: lines
+                    .next()
+                    .filter(|desktop| !desktop.is_empty())
+                    .map(str::to_string),
+            });
+        }
+
+        Ok(windows)
+    }
+
+    pub async fn activate(&self, window_id: &str) -> Result<()> {
+        super::output(&self.path, ["windowactivate", window_id]).await?;
+        Ok(())
+    }
+
+    pub async fn close(&self, window_id: &str) -> Result<()> {
+        super::output(&self.path, ["windowclose", window_id]).await?;
+        Ok(())
+    }
+
+    pub async fn minimize(&self, window_id: &str) -> Result<()> {
+        super::output(&self.path, ["windowminimize", window_id]).await?;
+        Ok(())
+    }
+
+    pub async fn toggle_fullscreen(&self, window_id: &str) -> Result<()> {
+        super::output(
+            &self.path,
+            ["windowstate", "--toggle", "FULLSCREEN", window_id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn move_to_workspace(&self, window_id: &str, workspace: Option<&str>) -> Result<()> {
+        let desktop = match workspace {
+            Some(workspace) => workspace.to_string(),
+            None => super::output(&self.path, ["get_desktop"]).await?,
+        };
+        super::output(
+            &self.path,
+            ["set_desktop_for_window", window_id, desktop.trim()],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+fn which_in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).exists()))
+}
+
+static CARGO_KDOTOOL_PATH: LazyLock<String> = LazyLock::new(|| {
+    let mut s = std::env::var("HOME").expect("HOME variable must be set");
+    s.push_str("/.cargo/bin/kdotool");
+    s
+});
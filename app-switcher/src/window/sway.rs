@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use covey_plugin::{Result, anyhow::Context};
+use serde_json::Value;
+
+use super::Window;
+
+/// Controls windows with `swaymsg` on Sway, or `i3-msg` on i3.
+#[derive(Debug, Clone)]
+pub struct Sway {
+    path: PathBuf,
+}
+
+impl Sway {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub async fn windows(&self) -> Result<Vec<Window>> {
+        let tree: Value =
+            serde_json::from_str(&super::output(&self.path, ["-t", "get_tree", "-r"]).await?)?;
+
+        let mut windows = Vec::new();
+        collect_windows(&tree, None, &mut windows);
+        Ok(windows)
+    }
+
+    pub async fn activate(&self, window_id: &str) -> Result<()> {
+        self.run_command(window_id, "focus").await
+    }
+
+    pub async fn close(&self, window_id: &str) -> Result<()> {
+        self.run_command(window_id, "kill").await
+    }
+
+    /// Sway and i3 don't have minimizing, so this moves the window to the
+    /// scratchpad instead.
+    pub async fn minimize(&self, window_id: &str) -> Result<()> {
+        self.run_command(window_id, "move scratchpad").await
+    }
+
+    pub async fn toggle_fullscreen(&self, window_id: &str) -> Result<()> {
+        self.run_command(window_id, "fullscreen toggle").await
+    }
+
+    /// Moves a window to the workspace with a name, which is created if it
+    /// doesn't exist yet.
+    pub async fn move_to_workspace(&self, window_id: &str, workspace: Option<&str>) -> Result<()> {
+        let name = match workspace {
+            Some(name) => name.to_string(),
+            None => self.focused_workspace().await?,
+        };
+
+        let name = serde_json::to_string(&name)?;
+        self.run_command(window_id, &format!("move container to workspace {name}"))
+            .await
+    }
+
+    async fn focused_workspace(&self) -> Result<String> {
+        let workspaces: Vec<Value> =
+            serde_json::from_str(&super::output(&self.path, ["-t", "get_workspaces"]).await?)?;
+        workspaces
+            .iter()
+            .find(|workspace| workspace["focused"] == true)
+            .and_then(|workspace| workspace["name"].as_str())
+            .map(str::to_string)
+            .context("no workspace is focused")
+    }
+
+    /// Runs a command on a specific window.
+    async fn run_command(&self, window_id: &str, command: &str) -> Result<()> {
+        super::output(&self.path, [format!("[con_id={window_id}] {command}")]).await?;
+        Ok(())
+    }
+}
+
+/// Walks the layout tree, collecting every container that holds a window.
+fn collect_windows(node: &Value, workspace: Option<&str>, windows: &mut Vec<Window>) {
+    let workspace = if node["type"] == "workspace" {
+        node["name"].as_str()
+    } else {
+        workspace
+    };
+
+    // native wayland windows have an app_id, xwayland (and all i3) windows
+    // have an X11 window id.
+    let class = node["app_id"]
+        .as_str()
+        .or_else(|| node["window_properties"]["class"].as_str());
+    if let (Some(id), Some(class)) = (node["id"].as_u64(), class) {
+        windows.push(Window {
+            id: id.to_string(),
+            title: node["name"].as_str().unwrap_or_default().to_string(),
+            class: class.to_string(),
+            workspace: workspace.map(str::to_string),
+        });
+    }
+
+    for child in ["nodes", "floating_nodes"]
+        .into_iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+    {
+        collect_windows(child, workspace, windows);
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use covey_plugin::{Result, anyhow::Context};
+
+use super::Window;
+
+/// Controls windows with `wmctrl` on X11, and `xdotool` for minimizing.
+#[derive(Debug, Clone)]
+pub struct X11 {
+    wmctrl: PathBuf,
+    xdotool: PathBuf,
+}
+
+impl X11 {
+    pub fn new(wmctrl: impl Into<PathBuf>, xdotool: impl Into<PathBuf>) -> Self {
+        Self {
+            wmctrl: wmctrl.into(),
+            xdotool: xdotool.into(),
+        }
+    }
+
+    pub async fn windows(&self) -> Result<Vec<Window>> {
+        let output = super::output(&self.wmctrl, ["-l", "-x"]).await?;
+        Ok(output.lines().filter_map(parse_wmctrl_line).collect())
+    }
+
+    pub async fn activate(&self, window_id: &str) -> Result<()> {
+        super::output(&self.wmctrl, ["-i", "-a", window_id]).await?;
+        Ok(())
+    }
+
+    pub async fn close(&self, window_id: &str) -> Result<()> {
+        super::output(&self.wmctrl, ["-i", "-c", window_id]).await?;
+        Ok(())
+    }
+
+    /// wmctrl can't minimize windows, so this uses xdotool.
+    pub async fn minimize(&self, window_id: &str) -> Result<()> {
+        super::output(&self.xdotool, ["windowminimize", window_id]).await?;
+        Ok(())
+    }
+
+    pub async fn toggle_fullscreen(&self, window_id: &str) -> Result<()> {
+        super::output(
+            &self.wmctrl,
+            ["-i", "-r", window_id, "-b", "toggle,fullscreen"],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Moves a window to a desktop, which are numbered from 1 like in
+    /// [`X11::windows`].
+    pub async fn move_to_workspace(&self, window_id: &str, workspace: Option<&str>) -> Result<()> {
+        let Some(workspace) = workspace else {
+            // -R moves the window to the current desktop before activating it
+            super::output(&self.wmctrl, ["-i", "-R", window_id]).await?;
+            return Ok(());
+        };
+
+        // wmctrl numbers desktops from 0
+        let desktop = workspace
+            .parse::<u32>()
+            .ok()
+            .and_then(|desktop| desktop.checked_sub(1))
+            .with_context(|| format!("X11 desktops are numbered from 1, not {workspace:?}"))?;
+        super::output(
+            &self.wmctrl,
+            ["-i", "-r", window_id, "-t", &desktop.to_string()],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Parses a line of `wmctrl -l -x`, which has the columns
+/// `<id> <desktop> <instance>.<class> <hostname> <title>`.
+fn parse_wmctrl_line(line: &str) -> Option<Window> {
+    let (id, rest) = split_column(line)?;
+    let (desktop, rest) = split_column(rest)?;
+    let (wm_class, rest) = split_column(rest)?;
+    let (_hostname, title) = split_column(rest).unwrap_or((rest, ""));
+
+    // -1 is for sticky windows which are on every desktop
+    let workspace = desktop
+        .parse::<u32>()
+        .ok()
+        .map(|desktop| (desktop + 1).to_string());
+
+    Some(Window {
+        id: id.to_string(),
+        title: title.to_string(),
+        class: wm_class
+            .rsplit_once('.')
+            .map_or(wm_class, |(_instance, class)| class)
+            .to_string(),
+        workspace,
+    })
+}
+
+/// Splits off the first whitespace-separated column.
+fn split_column(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_start();
+    if s.is_empty() {
+        return None;
+    }
+    let (column, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    Some((column, rest.trim_start()))
+}
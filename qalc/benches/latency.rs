@@ -0,0 +1,54 @@
+//! Compares starting qalc for every query with sending queries to a qalc
+//! session.
+//!
+//! Needs qalc to be installed. Run with `cargo bench -p qalc`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use qalc::session::{self, Session};
+use tokio::runtime;
+
+const EXPRESSIONS: [&str; 4] = ["1+1", "5 m to ft", "sqrt(2) * pi", "30!"];
+
+fn latency(c: &mut Criterion) {
+    let Ok(qalc_path) = which::which("qalc") else {
+        eprintln!("qalc isn't installed, skipping");
+        return;
+    };
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut session = Session::new(qalc_path.clone());
+
+    let mut group = c.benchmark_group("evaluate");
+    for expression in EXPRESSIONS {
+        let once = runtime
+            .block_on(session::evaluate_once(&qalc_path, &[], expression))
+            .unwrap();
+        let in_session = runtime.block_on(session.evaluate(expression)).unwrap();
+        if once != in_session {
+            eprintln!("session output is different: {once:?} != {in_session:?}");
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("two processes", expression),
+            expression,
+            |b, expression| {
+                b.iter(|| {
+                    runtime
+                        .block_on(session::evaluate_once(&qalc_path, &[], expression))
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("session", expression),
+            expression,
+            |b, expression| b.iter(|| runtime.block_on(session.evaluate(expression)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, latency);
+criterion_main!(benches);
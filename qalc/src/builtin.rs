@@ -0,0 +1,877 @@
+//! A small calculator for when qalc isn't installed, which can also answer
+//! simple arithmetic without waiting for qalc.
+//!
+//! It knows arithmetic, percentages, common functions and constants, integers
+//! in other bases and units of length, mass, time and volume.
+
+use std::{f64::consts, fmt, iter::Peekable, slice, str::Chars};
+
+use qalc::session::{Evaluation, Message, MessageKind};
+
+/// Evaluates an expression, like `5 km / 2 h to m/s`.
+///
+/// Errors are returned as an evaluation with an error message, like qalc's
+/// errors.
+pub fn evaluate(expression: &str) -> Evaluation {
+    match try_evaluate(expression) {
+        Ok((input, result)) => {
+            let equation = format!("{input} = {result}");
+            Evaluation {
+                output: equation.clone(),
+                equation,
+                terse: result,
+                messages: Vec::new(),
+            }
+        }
+        Err(e) => Evaluation {
+            output: String::new(),
+            equation: String::new(),
+            terse: String::new(),
+            messages: vec![Message {
+                kind: MessageKind::Error,
+                text: e,
+            }],
+        },
+    }
+}
+
+/// Whether an expression is arithmetic on whole numbers that this gives the
+/// same result for as qalc.
+///
+/// Every step has to be a whole number that an `f64` holds exactly, so
+/// large numbers and divisions with a remainder, which qalc keeps exact, are
+/// left to qalc.
+pub fn is_trivial(expression: &str) -> bool {
+    let Ok(tokens) = lex(expression) else {
+        return false;
+    };
+    let mut tokens = tokens.iter().peekable();
+    exact_sum(&mut tokens).is_some() && tokens.peek().is_none()
+}
+
+/// Whole numbers up to this size are held exactly by an `f64`.
+const MAX_EXACT: i64 = 1 << 53;
+
+type Tokens<'a> = Peekable<slice::Iter<'a, Token>>;
+
+fn exact(value: Option<i64>) -> Option<i64> {
+    value.filter(|value| value.abs() <= MAX_EXACT)
+}
+
+fn exact_sum(tokens: &mut Tokens<'_>) -> Option<i64> {
+    let mut value = exact_product(tokens)?;
+    loop {
+        value = if tokens.next_if_eq(&&Token::Op('+')).is_some() {
+            exact(value.checked_add(exact_product(tokens)?))?
+        } else if tokens.next_if_eq(&&Token::Op('-')).is_some() {
+            exact(value.checked_sub(exact_product(tokens)?))?
+        } else {
+            return Some(value);
+        };
+    }
+}
+
+fn exact_product(tokens: &mut Tokens<'_>) -> Option<i64> {
+    let mut value = exact_signed(tokens)?;
+    loop {
+        value = if tokens.next_if_eq(&&Token::Op('*')).is_some() {
+            exact(value.checked_mul(exact_signed(tokens)?))?
+        } else if tokens.next_if_eq(&&Token::Op('/')).is_some() {
+            let divisor = exact_signed(tokens)?;
+            if divisor == 0 || value % divisor != 0 {
+                return None;
+            }
+            value / divisor
+        } else {
+            return Some(value);
+        };
+    }
+}
+
+fn exact_signed(tokens: &mut Tokens<'_>) -> Option<i64> {
+    if tokens.next_if_eq(&&Token::Op('-')).is_some() {
+        Some(-exact_signed(tokens)?)
+    } else if tokens.next_if_eq(&&Token::Op('+')).is_some() {
+        exact_signed(tokens)
+    } else {
+        exact_power(tokens)
+    }
+}
+
+fn exact_power(tokens: &mut Tokens<'_>) -> Option<i64> {
+    let base = match tokens.next()? {
+        Token::Number(number) if number.fract() == 0.0 => exact(Some(*number as i64))?,
+        Token::Op('(') => {
+            let value = exact_sum(tokens)?;
+            tokens.next_if_eq(&&Token::Op(')'))?;
+            value
+        }
+        _ => return None,
+    };
+    if tokens.next_if_eq(&&Token::Op('^')).is_none() {
+        return Some(base);
+    }
+    let exponent = u32::try_from(exact_signed(tokens)?).ok()?;
+    exact(base.checked_pow(exponent))
+}
+
+type Result<T, E = String> = std::result::Result<T, E>;
+
+/// Evaluates an expression, returning the part before any conversion and
+/// the result.
+fn try_evaluate(expression: &str) -> Result<(String, String)> {
+    let (input, target) = match expression.rsplit_once(" to ") {
+        Some((input, target)) => (input.trim(), Some(target.trim())),
+        None => (expression.trim(), None),
+    };
+
+    let value = Parser::new(input)?.parse_all()?;
+    let result = match target {
+        None => value.to_string(),
+        Some("hex" | "hexadecimal") => format_base(&value, 16, "0x")?,
+        Some("oct" | "octal") => format_base(&value, 8, "0o")?,
+        Some("bin" | "binary") => format_base(&value, 2, "0b")?,
+        Some("sci" | "scientific") => {
+            format!("{}{}", format_scientific(value.number), value.unit_suffix())
+        }
+        Some(target) => {
+            let unit = Parser::new(target)?.parse_all()?;
+            if unit.dimensions != value.dimensions {
+                return Err(format!("can't convert {value} to {target}"));
+            }
+            format!("{} {target}", format_number(value.number / unit.number))
+        }
+    };
+    Ok((input.to_string(), result))
+}
+
+/// Exponents of the base dimensions of a quantity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Dimensions {
+    length: i8,
+    mass: i8,
+    time: i8,
+}
+
+impl Dimensions {
+    const NONE: Self = Self::new(0, 0, 0);
+    const LENGTH: Self = Self::new(1, 0, 0);
+    const MASS: Self = Self::new(0, 1, 0);
+    const TIME: Self = Self::new(0, 0, 1);
+    const VOLUME: Self = Self::new(3, 0, 0);
+
+    const fn new(length: i8, mass: i8, time: i8) -> Self {
+        Self { length, mass, time }
+    }
+
+    fn zip(self, other: Self, f: impl Fn(i8, i8) -> i8) -> Self {
+        Self::new(
+            f(self.length, other.length),
+            f(self.mass, other.mass),
+            f(self.time, other.time),
+        )
+    }
+
+    fn map(self, f: impl Fn(i8) -> Option<i8>) -> Option<Self> {
+        Some(Self::new(f(self.length)?, f(self.mass)?, f(self.time)?))
+    }
+}
+
+/// A number with units, which are stored in SI base units.
+#[derive(Debug, Clone, Copy)]
+struct Quantity {
+    number: f64,
+    dimensions: Dimensions,
+    /// Whether this was written as a percentage, so that `100 + 5%` can
+    /// add 5% of 100.
+    percent: bool,
+}
+
+impl Quantity {
+    fn number(number: f64) -> Self {
+        Self::with_dimensions(number, Dimensions::NONE)
+    }
+
+    fn with_dimensions(number: f64, dimensions: Dimensions) -> Self {
+        Self {
+            number,
+            dimensions,
+            percent: false,
+        }
+    }
+
+    fn is_dimensionless(&self) -> bool {
+        self.dimensions == Dimensions::NONE
+    }
+
+    fn dimensionless(self, what: &str) -> Result<f64> {
+        if self.is_dimensionless() {
+            Ok(self.number)
+        } else {
+            Err(format!("{what} can't have units"))
+        }
+    }
+
+    fn add(self, other: Self, sign: f64) -> Result<Self> {
+        if other.percent && !self.percent {
+            return Ok(Self::with_dimensions(
+                self.number * (1.0 + sign * other.number),
+                self.dimensions,
+            ));
+        }
+        if self.dimensions != other.dimensions {
+            return Err(format!("can't add {self} and {other}"));
+        }
+        Ok(Self::with_dimensions(
+            self.number + sign * other.number,
+            self.dimensions,
+        ))
+    }
+
+    fn multiply(self, other: Self) -> Self {
+        Self::with_dimensions(
+            self.number * other.number,
+            self.dimensions.zip(other.dimensions, |a, b| a + b),
+        )
+    }
+
+    fn divide(self, other: Self) -> Self {
+        Self::with_dimensions(
+            self.number / other.number,
+            self.dimensions.zip(other.dimensions, |a, b| a - b),
+        )
+    }
+
+    fn power(self, exponent: Self) -> Result<Self> {
+        let exponent = exponent.dimensionless("an exponent")?;
+        if self.is_dimensionless() {
+            return Ok(Self::number(self.number.powf(exponent)));
+        }
+        if exponent.fract() != 0.0 || exponent.abs() > f64::from(i8::MAX) {
+            return Err("units can only be raised to whole powers".to_string());
+        }
+        let dimensions = self
+            .dimensions
+            .map(|d| d.checked_mul(exponent as i8))
+            .ok_or("the units are too large")?;
+        Ok(Self::with_dimensions(
+            self.number.powf(exponent),
+            dimensions,
+        ))
+    }
+
+    /// Takes the `n`th root, which keeps units if their powers are multiples
+    /// of `n`.
+    fn root(self, n: i8) -> Result<Self> {
+        let dimensions = self
+            .dimensions
+            .map(|d| (d % n == 0).then_some(d / n))
+            .ok_or("can't take the root of these units")?;
+        let number = match n {
+            2 => self.number.sqrt(),
+            3 => self.number.cbrt(),
+            n => self.number.powf(1.0 / f64::from(n)),
+        };
+        Ok(Self::with_dimensions(number, dimensions))
+    }
+
+    /// The units in SI base units, like ` m/s^2`, or nothing if this has no
+    /// units.
+    fn unit_suffix(&self) -> String {
+        let units = [
+            ("m", self.dimensions.length),
+            ("kg", self.dimensions.mass),
+            ("s", self.dimensions.time),
+        ];
+        let format = |power: i8| {
+            let units: Vec<_> = units
+                .iter()
+                .filter(|(_, p)| p.signum() == power.signum() && *p != 0)
+                .map(|(unit, p)| match p.abs() {
+                    1 => unit.to_string(),
+                    p => format!("{unit}^{p}"),
+                })
+                .collect();
+            units.join("·")
+        };
+
+        let numerator = format(1);
+        let denominator = format(-1);
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (true, true) => String::new(),
+            (false, true) => format!(" {numerator}"),
+            (true, false) => format!(" 1/{denominator}"),
+            (false, false) => format!(" {numerator}/{denominator}"),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", format_number(self.number), self.unit_suffix())
+    }
+}
+
+/// Formats a number with up to 10 significant digits, like qalc.
+fn format_number(number: f64) -> String {
+    if number.is_nan() {
+        return "undefined".to_string();
+    }
+    if number.is_infinite() {
+        return if number > 0.0 {
+            "infinity"
+        } else {
+            "-infinity"
+        }
+        .to_string();
+    }
+
+    let magnitude = number.abs();
+    if magnitude != 0.0 && !(1e-6..1e15).contains(&magnitude) {
+        return format_scientific(number);
+    }
+    if number.fract() == 0.0 {
+        return format!("{number:.0}");
+    }
+
+    // digits after the point for 10 significant digits in total
+    let digits = (9 - magnitude.log10().floor() as i32).max(0);
+    trim_zeros(&format!("{number:.*}", digits as usize))
+}
+
+fn format_scientific(number: f64) -> String {
+    let formatted = format!("{number:.9E}");
+    let (mantissa, exponent) = formatted.split_once('E').unwrap_or((&formatted, "0"));
+    format!("{}E{exponent}", trim_zeros(mantissa))
+}
+
+fn trim_zeros(number: &str) -> String {
+    if number.contains('.') {
+        number
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+fn format_base(value: &Quantity, radix: u32, prefix: &str) -> Result<String> {
+    let number = value.dimensionless("a number in another base")?;
+    if number.fract() != 0.0 || number.abs() >= 2f64.powi(63) {
+        return Err("only integers can be converted to another base".to_string());
+    }
+    let integer = number as i64;
+    let digits = match radix {
+        16 => format!("{:X}", integer.unsigned_abs()),
+        8 => format!("{:o}", integer.unsigned_abs()),
+        _ => format!("{:b}", integer.unsigned_abs()),
+    };
+    let sign = if integer < 0 { "-" } else { "" };
+    Ok(format!("{sign}{prefix}{digits}"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Op(char),
+}
+
+fn lex(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            tokens.push(Token::Number(lex_number(&mut chars)?));
+        } else if c.is_alphabetic() || c == '_' {
+            let identifier = take_while(&mut chars, |c| c.is_alphanumeric() || c == '_');
+            tokens.push(Token::Identifier(identifier));
+        } else {
+            chars.next();
+            let op = match c {
+                '×' | '·' => '*',
+                '÷' => '/',
+                '−' => '-',
+                '*' if chars.next_if_eq(&'*').is_some() => '^',
+                '+' | '-' | '*' | '/' | '^' | '(' | ')' | ',' | '!' | '%' => c,
+                _ => return Err(format!("unexpected \"{c}\"")),
+            };
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+fn lex_number(chars: &mut Peekable<Chars<'_>>) -> Result<f64> {
+    let mut number = take_while(chars, |c| c.is_ascii_digit() || c == '.');
+
+    if number == "0" {
+        let radix = match chars.peek() {
+            Some('x' | 'X') => Some(16),
+            Some('o' | 'O') => Some(8),
+            Some('b' | 'B') => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            chars.next();
+            let digits = take_while(chars, |c| c.is_ascii_alphanumeric());
+            return i64::from_str_radix(&digits, radix)
+                .map(|n| n as f64)
+                .map_err(|_| format!("invalid base {radix} number \"{digits}\""));
+        }
+    }
+
+    // an exponent, but not the constant e like in 2e
+    let mut lookahead = chars.clone();
+    if lookahead.next_if(|c| matches!(c, 'e' | 'E')).is_some() {
+        lookahead.next_if(|c| matches!(c, '+' | '-'));
+        if lookahead.peek().is_some_and(char::is_ascii_digit) {
+            chars.next();
+            number.push('e');
+            if let Some(sign) = chars.next_if(|c| matches!(c, '+' | '-')) {
+                number.push(sign);
+            }
+            number.push_str(&take_while(chars, |c| c.is_ascii_digit()));
+        }
+    }
+
+    number
+        .parse()
+        .map_err(|_| format!("invalid number \"{number}\""))
+}
+
+fn take_while(chars: &mut Peekable<Chars<'_>>, f: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    while let Some(c) = chars.next_if(|c| f(*c)) {
+        taken.push(c);
+    }
+    taken
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(expression: &str) -> Result<Self> {
+        Ok(Self {
+            tokens: lex(expression)?,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        let matches = self.peek() == Some(&Token::Op(op));
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn parse_all(&mut self) -> Result<Quantity> {
+        if self.tokens.is_empty() {
+            return Err("nothing to calculate".to_string());
+        }
+        let value = self.sum()?;
+        match self.peek() {
+            None => Ok(value),
+            Some(token) => Err(format!("unexpected {token}")),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Quantity> {
+        let mut value = self.product()?;
+        loop {
+            let sign = if self.eat('+') {
+                1.0
+            } else if self.eat('-') {
+                -1.0
+            } else {
+                return Ok(value);
+            };
+            value = value.add(self.product()?, sign)?;
+        }
+    }
+
+    fn product(&mut self) -> Result<Quantity> {
+        let mut value = self.implicit_product()?;
+        loop {
+            if self.eat('*') {
+                value = value.multiply(self.implicit_product()?);
+            } else if self.eat('/') {
+                value = value.divide(self.implicit_product()?);
+            } else if self.peek() == Some(&Token::Identifier("of".to_string())) {
+                // like 20% of 50
+                self.position += 1;
+                value = value.multiply(self.implicit_product()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Values next to each other, like `5 km` or `2 pi`, which are
+    /// multiplied before anything else so that `5 km / 2 h` is a speed.
+    fn implicit_product(&mut self) -> Result<Quantity> {
+        let mut value = self.signed()?;
+        loop {
+            let continues = match self.peek() {
+                Some(Token::Number(_) | Token::Op('(')) => true,
+                Some(Token::Identifier(identifier)) => identifier != "of",
+                _ => false,
+            };
+            if !continues {
+                return Ok(value);
+            }
+            value = value.multiply(self.signed()?);
+        }
+    }
+
+    fn signed(&mut self) -> Result<Quantity> {
+        if self.eat('-') {
+            let value = self.signed()?;
+            Ok(Quantity {
+                number: -value.number,
+                ..value
+            })
+        } else if self.eat('+') {
+            self.signed()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Quantity> {
+        let base = self.postfix()?;
+        if self.eat('^') {
+            base.power(self.signed()?)
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Quantity> {
+        let mut value = self.primary()?;
+        loop {
+            if self.eat('!') {
+                value = Quantity::number(factorial(value.dimensionless("a factorial")?)?);
+            } else if self.eat('%') {
+                value = Quantity {
+                    percent: true,
+                    ..Quantity::number(value.dimensionless("a percentage")? / 100.0)
+                };
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Quantity> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Quantity::number(number)),
+            Some(Token::Op('(')) => {
+                let value = self.sum()?;
+                if !self.eat(')') {
+                    return Err("missing \")\"".to_string());
+                }
+                Ok(value)
+            }
+            Some(Token::Identifier(name)) => {
+                if self.eat('(') {
+                    let arguments = self.arguments()?;
+                    call(&name, &arguments)
+                } else {
+                    constant(&name)
+                        .or_else(|| unit(&name))
+                        .ok_or_else(|| format!("unknown variable or unit \"{name}\""))
+                }
+            }
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    /// Parses arguments after the opening parenthesis of a function call.
+    fn arguments(&mut self) -> Result<Vec<Quantity>> {
+        let mut arguments = Vec::new();
+        if self.eat(')') {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.sum()?);
+            if self.eat(')') {
+                return Ok(arguments);
+            }
+            if !self.eat(',') {
+                return Err("missing \")\"".to_string());
+            }
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "\"{number}\""),
+            Self::Identifier(identifier) => write!(f, "\"{identifier}\""),
+            Self::Op(op) => write!(f, "\"{op}\""),
+        }
+    }
+}
+
+fn factorial(n: f64) -> Result<f64> {
+    if n.fract() != 0.0 || n < 0.0 {
+        return Err("factorials are only of whole numbers".to_string());
+    }
+    Ok((1..=n.min(171.0) as u32).map(f64::from).product())
+}
+
+fn constant(name: &str) -> Option<Quantity> {
+    let number = match name {
+        "pi" | "π" => consts::PI,
+        "tau" => consts::TAU,
+        "e" => consts::E,
+        _ => return None,
+    };
+    Some(Quantity::number(number))
+}
+
+/// Looks up a unit, including SI prefixes on metric units.
+fn unit(name: &str) -> Option<Quantity> {
+    const METRIC: [(&str, f64, Dimensions); 5] = [
+        ("m", 1.0, Dimensions::LENGTH),
+        ("g", 1e-3, Dimensions::MASS),
+        ("s", 1.0, Dimensions::TIME),
+        ("l", 1e-3, Dimensions::VOLUME),
+        ("L", 1e-3, Dimensions::VOLUME),
+    ];
+    const PREFIXES: [(&str, f64); 10] = [
+        ("T", 1e12),
+        ("G", 1e9),
+        ("M", 1e6),
+        ("k", 1e3),
+        ("d", 1e-1),
+        ("c", 1e-2),
+        ("m", 1e-3),
+        ("µ", 1e-6),
+        ("u", 1e-6),
+        ("n", 1e-9),
+    ];
+    const OTHER: [(&str, f64, Dimensions); 15] = [
+        ("in", 0.0254, Dimensions::LENGTH),
+        ("ft", 0.3048, Dimensions::LENGTH),
+        ("yd", 0.9144, Dimensions::LENGTH),
+        ("mi", 1609.344, Dimensions::LENGTH),
+        ("t", 1000.0, Dimensions::MASS),
+        ("lb", 0.45359237, Dimensions::MASS),
+        ("oz", 0.028349523125, Dimensions::MASS),
+        ("min", 60.0, Dimensions::TIME),
+        ("h", 3600.0, Dimensions::TIME),
+        ("d", 86400.0, Dimensions::TIME),
+        ("day", 86400.0, Dimensions::TIME),
+        ("days", 86400.0, Dimensions::TIME),
+        ("week", 604800.0, Dimensions::TIME),
+        ("weeks", 604800.0, Dimensions::TIME),
+        ("gal", 0.003785411784, Dimensions::VOLUME),
+    ];
+
+    let find = |units: &[(&str, f64, Dimensions)], name: &str| {
+        units
+            .iter()
+            .find(|(unit, _, _)| *unit == name)
+            .map(|&(_, factor, dimensions)| Quantity::with_dimensions(factor, dimensions))
+    };
+
+    find(&OTHER, name)
+        .or_else(|| find(&METRIC, name))
+        .or_else(|| {
+            PREFIXES.iter().find_map(|(prefix, factor)| {
+                let unit = find(&METRIC, name.strip_prefix(prefix)?)?;
+                Some(Quantity::with_dimensions(
+                    unit.number * factor,
+                    unit.dimensions,
+                ))
+            })
+        })
+}
+
+fn call(name: &str, arguments: &[Quantity]) -> Result<Quantity> {
+    let one = || match arguments {
+        [argument] => Ok(*argument),
+        _ => Err(format!("{name} takes 1 argument")),
+    };
+    let number = || one()?.dimensionless(&format!("the argument of {name}"));
+    let keeping_units = |f: fn(f64) -> f64| {
+        let argument = one()?;
+        Ok(Quantity::with_dimensions(
+            f(argument.number),
+            argument.dimensions,
+        ))
+    };
+
+    let result = match name {
+        "sqrt" => return one()?.root(2),
+        "cbrt" => return one()?.root(3),
+        "abs" => return keeping_units(f64::abs),
+        "floor" => return keeping_units(f64::floor),
+        "ceil" => return keeping_units(f64::ceil),
+        "round" => return keeping_units(f64::round),
+        "sin" => number()?.sin(),
+        "cos" => number()?.cos(),
+        "tan" => number()?.tan(),
+        "asin" => number()?.asin(),
+        "acos" => number()?.acos(),
+        "atan" => number()?.atan(),
+        "sinh" => number()?.sinh(),
+        "cosh" => number()?.cosh(),
+        "tanh" => number()?.tanh(),
+        "exp" => number()?.exp(),
+        "ln" => number()?.ln(),
+        "log10" => number()?.log10(),
+        "log2" => number()?.log2(),
+        // like qalc, the base is e unless it's given
+        "log" => match arguments {
+            [number, base] => {
+                let base = base.dimensionless("the base of log")?;
+                number.dimensionless("the argument of log")?.log(base)
+            }
+            _ => number()?.ln(),
+        },
+        _ => return Err(format!("unknown function \"{name}\"")),
+    };
+    Ok(Quantity::number(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(expression: &str) -> String {
+        let evaluation = evaluate(expression);
+        assert!(!evaluation.failed(), "{expression}: {evaluation:?}");
+        evaluation.terse
+    }
+
+    fn error(expression: &str) -> String {
+        let evaluation = evaluate(expression);
+        assert!(evaluation.failed(), "{expression}: {evaluation:?}");
+        evaluation.messages[0].text.clone()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(result("1+1"), "2");
+        assert_eq!(result("2 + 3 * 4"), "14");
+        assert_eq!(result("(2 + 3) × 4"), "20");
+        assert_eq!(result("-2^2"), "-4");
+        assert_eq!(result("2^-1"), "0.5");
+        assert_eq!(result("2^3^2"), "512");
+        assert_eq!(result("2**10"), "1024");
+        assert_eq!(result("10 / 3"), "3.333333333");
+        assert_eq!(result("2(3 + 4)"), "14");
+        assert_eq!(result("5!"), "120");
+        assert_eq!(result("1e3 + 1"), "1001");
+        assert_eq!(result("30!"), "2.652528598E32");
+        assert_eq!(result("0.1 + 0.2"), "0.3");
+        assert_eq!(result("1 / 3 × 1e-9"), "3.333333333E-10");
+        assert_eq!(result("1/0"), "infinity");
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        assert_eq!(result("sqrt(2)"), "1.414213562");
+        assert_eq!(result("2pi"), "6.283185307");
+        assert_eq!(result("2e"), "5.436563657");
+        assert_eq!(result("π"), "3.141592654");
+        assert_eq!(result("cos(0)"), "1");
+        assert_eq!(result("log(8, 2)"), "3");
+        assert_eq!(result("ln(e)"), "1");
+        assert_eq!(error("sin(1, 2)"), "sin takes 1 argument");
+        assert_eq!(error("foo(1)"), r#"unknown function "foo""#);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(result("50%"), "0.5");
+        assert_eq!(result("20% of 50"), "10");
+        assert_eq!(result("200 + 10%"), "220");
+        assert_eq!(result("200 - 10%"), "180");
+        assert_eq!(result("200 * 10%"), "20");
+    }
+
+    #[test]
+    fn bases() {
+        assert_eq!(result("0xff"), "255");
+        assert_eq!(result("0b101 + 0o10"), "13");
+        assert_eq!(result("255 to hex"), "0xFF");
+        assert_eq!(result("-5 to bin"), "-0b101");
+        assert_eq!(result("8 to oct"), "0o10");
+        assert_eq!(result("123456 to sci"), "1.23456E5");
+        assert_eq!(
+            error("1.5 to hex"),
+            "only integers can be converted to another base"
+        );
+        assert_eq!(error("0xfg"), r#"invalid base 16 number "fg""#);
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(result("5 km + 300 m"), "5300 m");
+        assert_eq!(result("5 km to m"), "5000 m");
+        assert_eq!(result("5 m to ft"), "16.40419948 ft");
+        assert_eq!(result("100 km / 2 h"), "13.88888889 m/s");
+        assert_eq!(result("100 km / 2 h to km/h"), "50 km/h");
+        assert_eq!(result("3 m * 2 m"), "6 m^2");
+        assert_eq!(result("sqrt(16 m^2)"), "4 m");
+        assert_eq!(result("1 gal to L"), "3.785411784 L");
+        assert_eq!(result("2 lb to g"), "907.18474 g");
+        assert_eq!(result("1 / 4 s"), "0.25 1/s");
+        assert_eq!(error("5 m + 2 s"), "can't add 5 m and 2 s");
+        assert_eq!(error("5 m to s"), "can't convert 5 m to s");
+        assert_eq!(error("5 foo"), r#"unknown variable or unit "foo""#);
+    }
+
+    #[test]
+    fn equations() {
+        let evaluation = evaluate("5 km to m");
+        assert_eq!(evaluation.equation, "5 km = 5000 m");
+        assert_eq!(evaluation.output, "5 km = 5000 m");
+        assert_eq!(error(""), "nothing to calculate");
+        assert_eq!(error("(1"), r#"missing ")""#);
+        assert_eq!(error("1 +"), "unexpected end of expression");
+        assert_eq!(error("1 $"), r#"unexpected "$""#);
+    }
+
+    #[test]
+    fn trivial_expressions() {
+        assert!(is_trivial("1+1"));
+        assert!(is_trivial("(2 + 3) × 8 / 2^3"));
+        assert!(is_trivial("-2^2 - 3^2^2"));
+        assert!(is_trivial("2^53 - 1"));
+        assert!(!is_trivial(""));
+        assert!(!is_trivial("2^64 + 1"));
+        assert!(!is_trivial("2^60 + 1 - 2^60"));
+        assert!(!is_trivial("1/3"));
+        assert!(!is_trivial("2^-1"));
+        assert!(!is_trivial("1.5 * 2"));
+        assert!(!is_trivial("(1 + 2"));
+        assert!(!is_trivial("5 m"));
+        assert!(!is_trivial("sqrt(2)"));
+        assert!(!is_trivial("50%"));
+        assert!(!is_trivial("1 $"));
+    }
+}
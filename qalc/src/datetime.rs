@@ -0,0 +1,365 @@
+//! Dates, times and time zones, like `now + 3 weeks`, `days until
+//! 2026-12-25` or `14:00 Europe/Berlin in Sydney`.
+//!
+//! Time zones are read from the system's tzdb.
+
+use jiff::{
+    RoundMode, Span, Timestamp, Unit, Zoned, ZonedDifference,
+    civil::{Date, DateTime, Time},
+    fmt::friendly::{Designator, Spacing, SpanPrinter},
+    tz::{self, TimeZone},
+};
+
+/// One way of writing an answer, like as a unix timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    pub name: String,
+    pub text: String,
+}
+
+impl Format {
+    fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+}
+
+/// What a query asks for.
+#[derive(Debug, Clone)]
+enum Answer {
+    /// A point in time, in the time zone that it should be shown in.
+    Moment(Zoned),
+    /// How long it is from now until `target`, or since it if the span is
+    /// negative.
+    Duration { span: Span, target: Zoned },
+}
+
+/// Answers a date or time query in several formats, or returns `None` if
+/// the query isn't about dates or times.
+pub fn evaluate(query: &str, now: &Zoned) -> Option<Vec<Format>> {
+    let now = now.round(Unit::Second).ok()?;
+    Some(answer(query.trim(), &now)?.formats(&now))
+}
+
+fn answer(query: &str, now: &Zoned) -> Option<Answer> {
+    if let Some(duration) = duration(query, now) {
+        return Some(duration);
+    }
+    // `14:00 Europe/Berlin in Sydney`
+    for separator in [" in ", " to "] {
+        let Some((moment, zone)) = query.rsplit_once(separator) else {
+            continue;
+        };
+        if let (Some(moment), Some(zone)) = (expression(moment, now), time_zone(zone)) {
+            return Some(Answer::Moment(moment.with_time_zone(zone)));
+        }
+    }
+    expression(query, now).map(Answer::Moment)
+}
+
+/// `days until 2026-12-25` or `hours since 09:00`.
+fn duration(query: &str, now: &Zoned) -> Option<Answer> {
+    let (unit, rest) = query.split_once(char::is_whitespace)?;
+    let unit = match unit.to_lowercase().trim_end_matches('s') {
+        "year" => Unit::Year,
+        "month" => Unit::Month,
+        "week" => Unit::Week,
+        "day" => Unit::Day,
+        "hour" => Unit::Hour,
+        "minute" => Unit::Minute,
+        "second" => Unit::Second,
+        _ => return None,
+    };
+    let rest = rest.trim_start();
+    let (target, since) = if let Some(target) = rest.strip_prefix("until ") {
+        (target, false)
+    } else if let Some(target) = rest.strip_prefix("since ") {
+        (target, true)
+    } else {
+        return None;
+    };
+    let target = expression(target, now)?;
+
+    // counting days to a date shouldn't depend on the time of day now
+    let span = if unit >= Unit::Day && target.time() == Time::midnight() {
+        now.date().until((unit, target.date())).ok()?
+    } else {
+        let smallest = if unit == Unit::Second {
+            Unit::Second
+        } else {
+            Unit::Minute
+        };
+        now.until(
+            ZonedDifference::new(&target)
+                .largest(unit)
+                .smallest(smallest)
+                .mode(RoundMode::HalfExpand),
+        )
+        .ok()?
+    };
+    let span = if since { span.negate() } else { span };
+    Some(Answer::Duration { span, target })
+}
+
+/// A moment, optionally with a span added or subtracted, like `now + 3
+/// weeks`, `3 weeks ago` or `2 days from now`.
+fn expression(text: &str, now: &Zoned) -> Option<Zoned> {
+    let text = text.trim();
+    if let Some(span) = text.strip_suffix(" from now") {
+        return now.checked_add(span.parse::<Span>().ok()?).ok();
+    }
+    if text.ends_with(" ago") {
+        // jiff reads `3 weeks ago` as a negative span
+        return now.checked_add(text.parse::<Span>().ok()?).ok();
+    }
+    for (operator, sign) in [(" + ", 1), (" - ", -1)] {
+        if let Some((moment, span)) = text.split_once(operator) {
+            let span = span.trim().parse::<Span>().ok()?;
+            return zoned_moment(moment, now)?
+                .checked_add(span.checked_mul(sign).ok()?)
+                .ok();
+        }
+    }
+    zoned_moment(text, now)
+}
+
+/// A moment that may end with a time zone, like `14:00 Europe/Berlin`.
+fn zoned_moment(text: &str, now: &Zoned) -> Option<Zoned> {
+    let text = text.trim();
+    // try the longest zone first, for zones like `New York`
+    let zones = text
+        .char_indices()
+        .filter(|(_, c)| c.is_whitespace())
+        .filter_map(|(i, _)| Some((&text[..i], time_zone(&text[i..])?)));
+    for (moment, zone) in zones {
+        if let Some(moment) = moment_in(moment, &now.with_time_zone(zone)) {
+            return Some(moment);
+        }
+    }
+    moment_in(text, now)
+}
+
+/// A moment in the time zone of `now`, unless it says which zone it's in.
+fn moment_in(text: &str, now: &Zoned) -> Option<Zoned> {
+    let text = text.trim();
+    let zone = now.time_zone().clone();
+    let date_time = |date: Date| date.to_zoned(zone.clone()).ok();
+    match text.to_lowercase().as_str() {
+        "now" | "time" => return Some(now.clone()),
+        "today" => return date_time(now.date()),
+        "tomorrow" => return date_time(now.date().tomorrow().ok()?),
+        "yesterday" => return date_time(now.date().yesterday().ok()?),
+        _ => {}
+    }
+    if let Some(seconds) = text.strip_prefix('@') {
+        let timestamp = Timestamp::from_second(seconds.parse().ok()?).ok()?;
+        return Some(timestamp.to_zoned(zone));
+    }
+    // plain numbers would parse as dates or times in the basic ISO 8601
+    // format, but they're much more likely to be arithmetic
+    if !text.contains(['-', ':']) {
+        return time(text).and_then(|time| now.with().time(time).build().ok());
+    }
+
+    if let Ok(zoned) = text.parse::<Zoned>() {
+        Some(zoned)
+    } else if let Ok(timestamp) = text.parse::<Timestamp>() {
+        Some(timestamp.to_zoned(zone))
+    } else if let Ok(date_time) = text.parse::<DateTime>() {
+        date_time.to_zoned(zone).ok()
+    } else if let Ok(date) = text.parse::<Date>() {
+        date_time(date)
+    } else if let Some(time) = time(text) {
+        now.with().time(time).build().ok()
+    } else {
+        // `2026-12-25 2pm`
+        let (date, time_of_day) = text.split_once(char::is_whitespace)?;
+        let date_time = date.parse::<Date>().ok()?.to_datetime(time(time_of_day)?);
+        date_time.to_zoned(zone).ok()
+    }
+}
+
+/// A time of day, like `14:00`, `2pm` or `2:30 pm`.
+fn time(text: &str) -> Option<Time> {
+    let lower = text.to_lowercase();
+    let (clock, pm) = if let Some(clock) = lower.strip_suffix("am") {
+        (clock.trim(), false)
+    } else if let Some(clock) = lower.strip_suffix("pm") {
+        (clock.trim(), true)
+    } else if text.contains(':') {
+        return text.parse().ok();
+    } else {
+        return None;
+    };
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<i8>().ok()?, minute.parse().ok()?),
+        None => (clock.parse::<i8>().ok()?, 0),
+    };
+    if !(1..=12).contains(&hour) {
+        return None;
+    }
+    let hour = hour % 12 + if pm { 12 } else { 0 };
+    Time::new(hour, minute, 0, 0).ok()
+}
+
+/// A time zone from the tzdb, like `Europe/Berlin`, or a city in one, like
+/// `Sydney` or `new york`.
+fn time_zone(name: &str) -> Option<TimeZone> {
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    if name.eq_ignore_ascii_case("local") {
+        return Some(TimeZone::system());
+    }
+    if let Ok(zone) = tz::db().get(name) {
+        return Some(zone);
+    }
+
+    let city = name.replace(' ', "_");
+    let zone = tz::db().available().find(|zone| {
+        zone.as_str()
+            .rsplit('/')
+            .next()
+            .is_some_and(|zone_city| zone_city.eq_ignore_ascii_case(&city))
+    })?;
+    tz::db().get(zone.as_str()).ok()
+}
+
+impl Answer {
+    fn formats(&self, now: &Zoned) -> Vec<Format> {
+        match self {
+            Self::Moment(moment) => vec![
+                Format::new(
+                    format!("Date and time in {}", zone_name(moment)),
+                    moment.strftime("%A %-d %B %Y, %H:%M:%S %Z").to_string(),
+                ),
+                Format::new(
+                    "ISO 8601",
+                    moment
+                        .timestamp()
+                        .display_with_offset(moment.offset())
+                        .to_string(),
+                ),
+                Format::new("Unix timestamp", moment.timestamp().as_second().to_string()),
+                Format::new("Relative", relative(now, moment)),
+            ],
+            Self::Duration { span, target } => {
+                let direction = if span.is_negative() { "Since" } else { "Until" };
+                let seconds = target.timestamp().as_second() - now.timestamp().as_second();
+                vec![
+                    Format::new(
+                        format!("{direction} {}", target.strftime("%A %-d %B %Y, %H:%M %Z")),
+                        describe(&span.abs()),
+                    ),
+                    Format::new("ISO 8601 duration", span.abs().to_string()),
+                    Format::new("Seconds", seconds.abs().to_string()),
+                ]
+            }
+        }
+    }
+}
+
+fn zone_name(moment: &Zoned) -> &str {
+    moment.time_zone().iana_name().unwrap_or("local time")
+}
+
+/// How long it is until `moment`, like `in 3 days, 2 hours` or `5 minutes
+/// ago`.
+fn relative(now: &Zoned, moment: &Zoned) -> String {
+    let Ok(span) = now.until(
+        ZonedDifference::new(moment)
+            .largest(Unit::Year)
+            .smallest(Unit::Minute)
+            .mode(RoundMode::HalfExpand),
+    ) else {
+        return String::new();
+    };
+
+    if span.is_zero() {
+        "now".to_string()
+    } else if span.is_negative() {
+        format!("{} ago", describe(&span.abs()))
+    } else {
+        format!("in {}", describe(&span))
+    }
+}
+
+/// Writes out a span, like `3 days, 2 hours`.
+fn describe(span: &Span) -> String {
+    SpanPrinter::new()
+        .designator(Designator::Verbose)
+        .spacing(Spacing::BetweenUnitsAndDesignators)
+        .comma_after_designator(true)
+        .span_to_string(span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> Zoned {
+        "2026-10-18T12:00:00[Europe/Berlin]".parse().unwrap()
+    }
+
+    fn texts(query: &str) -> Vec<String> {
+        evaluate(query, &now())
+            .unwrap_or_else(|| panic!("{query:?} wasn't recognised"))
+            .into_iter()
+            .map(|format| format.text)
+            .collect()
+    }
+
+    #[test]
+    fn moments() {
+        assert_eq!(
+            texts("now + 3 weeks"),
+            [
+                "Sunday 8 November 2026, 12:00:00 CET",
+                "2026-11-08T12:00:00+01:00",
+                "1794135600",
+                "in 21 days",
+            ]
+        );
+        assert_eq!(texts("2 hours ago")[1], "2026-10-18T10:00:00+02:00");
+        assert_eq!(texts("2 days from now")[3], "in 2 days");
+        assert_eq!(texts("tomorrow - 1h")[1], "2026-10-18T23:00:00+02:00");
+        assert_eq!(texts("2026-10-17 9:30pm")[3], "14 hours, 30 minutes ago");
+        assert_eq!(texts("@0")[1], "1970-01-01T01:00:00+01:00");
+        assert_eq!(texts("now")[3], "now");
+    }
+
+    #[test]
+    fn time_zones() {
+        let sydney = evaluate("14:00 Europe/Berlin in Sydney", &now()).unwrap();
+        assert_eq!(sydney[0].name, "Date and time in Australia/Sydney");
+        assert_eq!(sydney[1].text, "2026-10-18T23:00:00+11:00");
+
+        assert_eq!(texts("9am new york to utc")[1], "2026-10-18T13:00:00+00:00");
+        assert_eq!(texts("time in Tokyo")[1], "2026-10-18T19:00:00+09:00");
+    }
+
+    #[test]
+    fn durations() {
+        let christmas = evaluate("days until 2026-12-25", &now()).unwrap();
+        assert_eq!(
+            christmas[0],
+            Format::new("Until Friday 25 December 2026, 00:00 CET", "68 days")
+        );
+        assert_eq!(christmas[1].text, "P68D");
+
+        assert_eq!(texts("weeks until 2026-12-25")[0], "9 weeks, 5 days");
+        assert_eq!(texts("hours since 09:15")[0], "2 hours, 45 minutes");
+        assert_eq!(texts("minutes until 12:30")[2], "1800");
+    }
+
+    #[test]
+    fn other_queries() {
+        for query in ["1 + 2", "20261225", "3 weeks", "5 m to ft", "ans + 3 days"] {
+            assert_eq!(evaluate(query, &now()), None, "{query:?}");
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Variables and functions defined from the launcher, like `rate := 85 USD/h`
+//! or `area(r) := pi r^2`, which are saved to the plugin's data dir.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use covey_plugin::{Result, anyhow::Context};
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+/// What qalc calls the arguments of a function, in order.
+const ARGUMENTS: [&str; 6] = ["x", "y", "z", "a", "b", "c"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Definition {
+    pub name: String,
+    /// Names of the parameters, if this is a function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Vec<String>>,
+    pub expression: String,
+}
+
+impl Definition {
+    /// Parses `name := expression` or `name(x, y) := expression`.
+    pub fn parse(query: &str) -> Option<Self> {
+        let (name, expression) = query.split_once(":=")?;
+        let expression = expression.trim();
+        if expression.is_empty() {
+            return None;
+        }
+
+        let (name, parameters) = match name.trim().split_once('(') {
+            Some((name, parameters)) => {
+                let parameters: Vec<_> = parameters
+                    .strip_suffix(')')?
+                    .split(',')
+                    .map(|parameter| parameter.trim().to_string())
+                    .filter(|parameter| !parameter.is_empty())
+                    .collect();
+                if parameters.len() > ARGUMENTS.len()
+                    || !parameters.iter().all(|parameter| is_identifier(parameter))
+                {
+                    return None;
+                }
+                (name.trim(), Some(parameters))
+            }
+            None => (name.trim(), None),
+        };
+        if !is_identifier(name) {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            parameters,
+            expression: expression.to_string(),
+        })
+    }
+
+    /// The qalc command that defines this.
+    pub fn command(&self) -> String {
+        match &self.parameters {
+            None => format!("variable {} {}", self.name, self.expression),
+            Some(parameters) => {
+                // qalc refers to arguments as \x, \y and so on
+                let expression = replace_identifiers(&self.expression, |identifier| {
+                    let index = parameters.iter().position(|p| p == identifier)?;
+                    Some(format!("\\{}", ARGUMENTS[index]))
+                });
+                format!("function {} {expression}", self.name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.parameters {
+            None => write!(f, "{} := {}", self.name, self.expression),
+            Some(parameters) => write!(
+                f,
+                "{}({}) := {}",
+                self.name,
+                parameters.join(", "),
+                self.expression
+            ),
+        }
+    }
+}
+
+/// All definitions, which are saved to a file whenever they change.
+#[derive(Debug, Clone)]
+pub struct Definitions {
+    path: PathBuf,
+    definitions: Arc<RwLock<Vec<Definition>>>,
+    /// Held while saving, so that saves don't overlap.
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Definitions {
+    /// Reads the definitions from `path`, starting with none if it doesn't
+    /// exist yet.
+    ///
+    /// If the file can't be read, there are no definitions and the file is
+    /// set aside instead of being overwritten.
+    pub async fn load(path: PathBuf) -> Self {
+        let definitions = match read_definitions(&path).await {
+            Ok(definitions) => definitions,
+            Err(e) => {
+                storage::set_aside(&path, &e).await;
+                Vec::new()
+            }
+        };
+
+        Self {
+            path,
+            definitions: Arc::new(RwLock::new(definitions)),
+            saving: Arc::default(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Definition> {
+        self.definitions.read().unwrap().clone()
+    }
+
+    /// Commands that define everything in qalc.
+    pub fn commands(&self) -> Vec<String> {
+        self.definitions
+            .read()
+            .unwrap()
+            .iter()
+            .map(Definition::command)
+            .collect()
+    }
+
+    /// The first defined name that `text` uses, if any.
+    pub fn first_used_in(&self, text: &str) -> Option<String> {
+        let definitions = self.definitions.read().unwrap();
+        let mut used = None;
+        replace_identifiers(text, |identifier| {
+            if used.is_none() && definitions.iter().any(|other| other.name == identifier) {
+                used = Some(identifier.to_string());
+            }
+            None
+        });
+        used
+    }
+
+    /// Adds a definition, replacing any other one with the same name.
+    pub async fn define(&self, definition: Definition) -> Result<()> {
+        self.update(|definitions| {
+            definitions.retain(|other| other.name != definition.name);
+            definitions.push(definition);
+        })
+        .await
+    }
+
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        self.update(|definitions| definitions.retain(|other| other.name != name))
+            .await
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut Vec<Definition>)) -> Result<()> {
+        f(&mut self.definitions.write().unwrap());
+
+        let _saving = self.saving.lock().await;
+        // read after waiting for other saves, so the newest definitions are
+        // always saved last.
+        let json = serde_json::to_vec(&*self.definitions.read().unwrap())?;
+        storage::write_atomically(&self.path, json)
+            .await
+            .context("failed to save definitions")
+    }
+}
+
+async fn read_definitions(path: &Path) -> Result<Vec<Definition>> {
+    match tokio::fs::read(path).await {
+        Ok(json) => serde_json::from_slice(&json)
+            .with_context(|| format!("invalid definitions file {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("failed to read definitions file"),
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Replaces each word in `text` that `replacement` returns something for.
+pub fn replace_identifiers(
+    text: &str,
+    mut replacement: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let mut replaced = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        replaced.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let identifier = &rest[..end];
+        match replacement(identifier) {
+            Some(replacement) => replaced.push_str(&replacement),
+            None => replaced.push_str(identifier),
+        }
+        rest = &rest[end..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_variables() {
+        let rate = Definition::parse("rate := 85 USD/h").unwrap();
+        assert_eq!(rate.name, "rate");
+        assert_eq!(rate.parameters, None);
+        assert_eq!(rate.command(), "variable rate 85 USD/h");
+        assert_eq!(rate.to_string(), "rate := 85 USD/h");
+
+        assert_eq!(Definition::parse("1+1"), None);
+        assert_eq!(Definition::parse("rate :="), None);
+        assert_eq!(Definition::parse("2rate := 5"), None);
+        assert_eq!(Definition::parse("a b := 5"), None);
+    }
+
+    #[test]
+    fn parse_functions() {
+        let area = Definition::parse("area(width, height) := width × height_2").unwrap();
+        assert_eq!(area.name, "area");
+        assert_eq!(
+            area.parameters,
+            Some(vec!["width".to_string(), "height".to_string()])
+        );
+        assert_eq!(area.command(), r"function area \x × height_2");
+        assert_eq!(area.to_string(), "area(width, height) := width × height_2");
+
+        let double = Definition::parse("double(n):=2n").unwrap();
+        assert_eq!(double.command(), r"function double 2\x");
+
+        assert_eq!(Definition::parse("f(x := x"), None);
+        assert_eq!(Definition::parse("f(1) := 1"), None);
+    }
+
+    #[tokio::test]
+    async fn saved_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("definitions.json");
+
+        let definitions = Definitions::load(path.clone()).await;
+        for query in ["rate := 85", "servers := 12", "rate := 90", "f(x) := x"] {
+            let definition = Definition::parse(query).unwrap();
+            definitions.define(definition).await.unwrap();
+        }
+        definitions.delete("servers").await.unwrap();
+
+        let definitions = Definitions::load(path).await;
+        assert_eq!(
+            definitions.commands(),
+            ["variable rate 90", r"function f \x"]
+        );
+        assert_eq!(
+            definitions.first_used_in("2 f(rate)"),
+            Some("f".to_string())
+        );
+        assert_eq!(definitions.first_used_in("servers * 2"), None);
+    }
+
+    #[tokio::test]
+    async fn sets_aside_unreadable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("definitions.json");
+        std::fs::write(&path, "rate := 85").unwrap();
+
+        let definitions = Definitions::load(path.clone()).await;
+        assert_eq!(definitions.list(), []);
+        let definition = Definition::parse("rate := 90").unwrap();
+        definitions.define(definition).await.unwrap();
+
+        let backup = std::fs::read_to_string(dir.path().join("definitions.json.bak")).unwrap();
+        assert_eq!(backup, "rate := 85");
+        assert_eq!(
+            Definitions::load(path).await.commands(),
+            ["variable rate 90"]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Keeping qalc's exchange rates up to date, without downloading them every
+//! time that the plugin starts.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, anyhow},
+};
+use qalc::session;
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use crate::{Config, exchange_rates::ExchangeRatesSelection, history::unix_time_now};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long to wait before trying again after an update failed, like when
+/// offline.
+const RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How long downloading the rates can take.
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// When to download new exchange rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdatePolicy {
+    Never,
+    OnStart,
+    /// When the rates are older than this.
+    Every(Duration),
+}
+
+impl UpdatePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        match config.exchange_rates {
+            ExchangeRatesSelection::Never => Self::Never,
+            ExchangeRatesSelection::OnStart => Self::OnStart,
+            ExchangeRatesSelection::EveryNDays => {
+                Self::Every(DAY * config.exchange_rates_days.unsigned_abs())
+            }
+        }
+    }
+}
+
+/// Saved in the plugin's data dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Record {
+    /// Seconds since the unix epoch of the last successful update.
+    updated: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExchangeRates {
+    qalc_path: PathBuf,
+    /// Where the [`Record`] is saved.
+    path: PathBuf,
+    policy: UpdatePolicy,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    record: Record,
+    updating: bool,
+    /// When the last update was started since the plugin started, even if
+    /// it failed.
+    attempted: Option<Instant>,
+}
+
+impl ExchangeRates {
+    /// Reads when the rates were last updated from `path`.
+    ///
+    /// The record is only informational, so if it can't be read the rates
+    /// are treated as never updated.
+    pub async fn load(qalc_path: PathBuf, path: PathBuf, policy: UpdatePolicy) -> Self {
+        let record = match read_record(&path).await {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{e:#}");
+                Record::default()
+            }
+        };
+
+        Self {
+            qalc_path,
+            path,
+            policy,
+            state: Arc::new(Mutex::new(State {
+                record,
+                ..State::default()
+            })),
+        }
+    }
+
+    /// Downloads new rates if the policy says that they are due.
+    ///
+    /// Returns whether the rates were updated.
+    pub async fn update_if_needed(&self, starting: bool) -> Result<bool> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if !self.is_due(&state, starting) {
+                return Ok(false);
+            }
+            state.updating = true;
+            state.attempted = Some(Instant::now());
+        }
+
+        let updated = time::timeout(
+            UPDATE_TIMEOUT,
+            session::update_exchange_rates(&self.qalc_path),
+        )
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("timed out")));
+
+        let json = {
+            let mut state = self.state.lock().unwrap();
+            state.updating = false;
+            updated?;
+            state.record.updated = Some(unix_time_now());
+            serde_json::to_vec(&state.record)?
+        };
+        tokio::fs::write(&self.path, json)
+            .await
+            .context("failed to save when exchange rates were updated")?;
+        Ok(true)
+    }
+
+    fn is_due(&self, state: &State, starting: bool) -> bool {
+        if state.updating
+            || state
+                .attempted
+                .is_some_and(|attempted| attempted.elapsed() < RETRY_DELAY)
+        {
+            return false;
+        }
+
+        match self.policy {
+            UpdatePolicy::Never => false,
+            UpdatePolicy::OnStart => starting,
+            UpdatePolicy::Every(interval) => state.record.updated.is_none_or(|updated| {
+                unix_time_now().saturating_sub(updated) >= interval.as_secs()
+            }),
+        }
+    }
+
+    /// Describes how old the rates are, for results with currencies.
+    pub fn description(&self) -> String {
+        let Some(updated) = self.state.lock().unwrap().record.updated else {
+            return "Exchange rates weren't downloaded by this plugin yet".to_string();
+        };
+
+        match unix_time_now().saturating_sub(updated) / DAY.as_secs() {
+            0 => "Exchange rates as of today".to_string(),
+            1 => "Exchange rates as of yesterday".to_string(),
+            days => format!("Exchange rates as of {days} days ago"),
+        }
+    }
+}
+
+async fn read_record(path: &Path) -> Result<Record> {
+    match tokio::fs::read(path).await {
+        Ok(json) => serde_json::from_slice(&json)
+            .with_context(|| format!("invalid exchange rates record {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Record::default()),
+        Err(e) => Err(e).context("failed to read exchange rates record"),
+    }
+}
+
+/// Whether some text, like an equation, mentions an amount of money.
+///
+/// qalc prints currencies as their ISO 4217 code, like `USD`, or their
+/// symbol, so this looks for words of three capital letters or currency
+/// symbols. Some other units like `BTU` also look like a currency.
+pub fn mentions_currency(text: &str) -> bool {
+    const SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹', '₽', '₩', '₺', '₪', '₫', '₱', '฿'];
+
+    text.contains(SYMBOLS)
+        || text
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn currencies() {
+        assert!(mentions_currency("5 USD = approx. 4.6 EUR"));
+        assert!(mentions_currency("10 € = 8.6 £"));
+        assert!(mentions_currency("(20 USD/h) × 8 h = 160 USD"));
+        assert!(!mentions_currency("5 m = approx. 16.40419948 ft"));
+        assert!(!mentions_currency("sqrt(2) = √(2) ≈ 1.414213562"));
+        assert!(!mentions_currency("2 GHz = 2000 MHz"));
+    }
+}
@@ -0,0 +1,467 @@
+//! Calculations that were copied or completed, saved to the plugin's data
+//! dir.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, bail},
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, definitions::replace_identifiers, storage};
+
+/// Version of the history file's format, which should be increased whenever
+/// it changes so that older files can be migrated.
+///
+/// Version 2 added [`HistoryEntry::added`].
+const VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub query: String,
+    pub equation: String,
+    pub result: String,
+    /// Favorites are never removed to keep the history short.
+    #[serde(default)]
+    pub favorite: bool,
+    /// Seconds since the unix epoch. Entries from before this was saved
+    /// count as added when they are loaded.
+    #[serde(default = "unix_time_now")]
+    pub added: u64,
+}
+
+impl HistoryEntry {
+    /// Whether this is the same calculation as `other`, even if only one of
+    /// them is a favorite.
+    fn same_as(&self, other: &Self) -> bool {
+        self.query == other.query && self.equation == other.equation && self.result == other.result
+    }
+}
+
+/// What the history file contains.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum HistoryFile {
+    Versioned {
+        version: u32,
+        entries: Vec<HistoryEntry>,
+    },
+    /// Before there was a version, the file was only a list of entries.
+    Unversioned(Vec<HistoryEntry>),
+}
+
+impl HistoryFile {
+    fn into_entries(self) -> Result<Vec<HistoryEntry>> {
+        match self {
+            Self::Versioned { version, entries } if version <= VERSION => Ok(entries),
+            Self::Versioned { version, .. } => {
+                bail!("history file version {version} is newer than this plugin")
+            }
+            Self::Unversioned(entries) => Ok(entries),
+        }
+    }
+}
+
+/// Which entries are kept.
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    /// Older entries are removed after this many, except for favorites.
+    pub max_entries: usize,
+    /// Entries are removed after this long, except for favorites.
+    pub max_age: Option<Duration>,
+    /// Queries that are never added.
+    pub exclude: GlobSet,
+}
+
+impl HistoryOptions {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let days = u64::from(config.history_max_age.unsigned_abs());
+        Ok(Self {
+            max_entries: config.history_length.try_into().unwrap_or(0),
+            max_age: (days > 0).then(|| Duration::from_secs(days * 24 * 60 * 60)),
+            exclude: glob_set(&config.history_exclude).context("invalid history-exclude glob")?,
+        })
+    }
+
+    /// Removes entries that shouldn't be kept any more.
+    fn prune(&self, entries: &mut Vec<HistoryEntry>) {
+        entries.retain(|entry| !self.exclude.is_match(&entry.query));
+        if let Some(max_age) = self.max_age {
+            let oldest = unix_time_now().saturating_sub(max_age.as_secs());
+            entries.retain(|entry| entry.favorite || entry.added >= oldest);
+        }
+        remove_oldest(entries, self.max_entries);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    /// Where the history is saved, or `None` to only keep it in memory.
+    path: Option<PathBuf>,
+    options: Arc<HistoryOptions>,
+    /// Oldest first.
+    entries: Arc<RwLock<Vec<HistoryEntry>>>,
+    /// Held while saving, so that saves don't overlap.
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl History {
+    /// Reads the history from `path`, starting with none if it doesn't exist
+    /// yet or if there is no path.
+    ///
+    /// If the file can't be read, the history starts empty and the file is
+    /// set aside instead of being overwritten.
+    pub async fn load(path: Option<PathBuf>, options: HistoryOptions) -> Self {
+        let mut entries = match &path {
+            Some(path) => match read_entries(path).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    storage::set_aside(path, &e).await;
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        options.prune(&mut entries);
+
+        Self {
+            path,
+            options: Arc::new(options),
+            entries: Arc::new(RwLock::new(entries)),
+            saving: Arc::default(),
+        }
+    }
+
+    /// All entries, newest first.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.read().unwrap().iter().rev().cloned().collect()
+    }
+
+    /// Replaces `ans1`, `ans2` and so on in `query` with the results of the
+    /// newest entries, newest first. `ans` is the same as `ans1`.
+    pub fn bind_answers(&self, query: &str) -> String {
+        let entries = self.entries.read().unwrap();
+        replace_identifiers(query, |identifier| {
+            let number = answer_number(identifier)?;
+            let entry = entries.iter().rev().nth(number.checked_sub(1)?)?;
+            Some(format!("({})", entry.result))
+        })
+    }
+
+    pub async fn add(
+        &self,
+        query: &str,
+        equation: impl Into<String>,
+        result: impl Into<String>,
+    ) -> Result<()> {
+        if self.options.exclude.is_match(query) {
+            return Ok(());
+        }
+        // only add to history if the query changed
+        if self
+            .entries
+            .read()
+            .unwrap()
+            .last()
+            .is_some_and(|last| last.query == query)
+        {
+            return Ok(());
+        }
+
+        self.update(|entries| {
+            entries.push(HistoryEntry {
+                query: query.to_string(),
+                equation: equation.into(),
+                result: result.into(),
+                favorite: false,
+                added: unix_time_now(),
+            });
+        })
+        .await
+    }
+
+    /// Removes every entry for the same calculation as `entry`.
+    pub async fn delete(&self, entry: &HistoryEntry) -> Result<()> {
+        self.update(|entries| entries.retain(|other| !other.same_as(entry)))
+            .await
+    }
+
+    pub async fn toggle_favorite(&self, entry: &HistoryEntry) -> Result<()> {
+        self.update(|entries| {
+            for other in entries.iter_mut().filter(|other| other.same_as(entry)) {
+                other.favorite = !entry.favorite;
+            }
+        })
+        .await
+    }
+
+    /// Removes every entry that isn't a favorite.
+    pub async fn clear(&self) -> Result<()> {
+        self.update(|entries| entries.retain(|entry| entry.favorite))
+            .await
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut Vec<HistoryEntry>)) -> Result<()> {
+        {
+            let mut entries = self.entries.write().unwrap();
+            f(&mut entries);
+            self.options.prune(&mut entries);
+        }
+        self.save().await.context("failed to save history")
+    }
+
+    async fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let _saving = self.saving.lock().await;
+        // the entries are read after waiting for other saves, so the newest
+        // entries are always saved last.
+        let json = {
+            let entries = self.entries.read().unwrap();
+            serde_json::to_vec(&HistoryFile::Versioned {
+                version: VERSION,
+                entries: entries.clone(),
+            })?
+        };
+
+        storage::write_atomically(path, json).await
+    }
+}
+
+async fn read_entries(path: &Path) -> Result<Vec<HistoryEntry>> {
+    match tokio::fs::read(path).await {
+        Ok(json) => serde_json::from_slice::<HistoryFile>(&json)
+            .map_err(Into::into)
+            .and_then(HistoryFile::into_entries)
+            .with_context(|| format!("invalid history file {}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("failed to read history file"),
+    }
+}
+
+/// Which answer an identifier like `ans2` refers to.
+fn answer_number(identifier: &str) -> Option<usize> {
+    let number = identifier.strip_prefix("ans")?;
+    if number.is_empty() {
+        Some(1)
+    } else if number.starts_with('0') {
+        None
+    } else {
+        number.parse().ok()
+    }
+}
+
+/// Removes the oldest entries that aren't favorites, until there are at most
+/// `max` of them.
+fn remove_oldest(entries: &mut Vec<HistoryEntry>, max: usize) {
+    let mut excess = entries
+        .iter()
+        .filter(|entry| !entry.favorite)
+        .count()
+        .saturating_sub(max);
+    entries.retain(|entry| {
+        if excess == 0 || entry.favorite {
+            return true;
+        }
+        excess -= 1;
+        false
+    });
+}
+
+pub fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Makes a glob set from a `;` separated config option.
+fn glob_set(globs: &str) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs
+        .split(';')
+        .map(str::trim)
+        .filter(|glob| !glob.is_empty())
+    {
+        builder.add(Glob::new(glob)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> HistoryOptions {
+        HistoryOptions {
+            max_entries: 500,
+            max_age: None,
+            exclude: GlobSet::empty(),
+        }
+    }
+
+    fn entry(query: &str, favorite: bool) -> HistoryEntry {
+        HistoryEntry {
+            query: query.to_string(),
+            equation: format!("{query} = 2"),
+            result: "2".to_string(),
+            favorite,
+            added: 0,
+        }
+    }
+
+    /// The queries of the entries, newest first, and whether they are
+    /// favorites.
+    fn queries(history: &History) -> Vec<(String, bool)> {
+        history
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.query, entry.favorite))
+            .collect()
+    }
+
+    #[test]
+    fn favorites_are_kept() {
+        let mut entries = vec![
+            entry("1", true),
+            entry("2", false),
+            entry("3", false),
+            entry("4", true),
+            entry("5", false),
+        ];
+        remove_oldest(&mut entries, 2);
+        assert_eq!(
+            entries,
+            [
+                entry("1", true),
+                entry("3", false),
+                entry("4", true),
+                entry("5", false)
+            ]
+        );
+
+        remove_oldest(&mut entries, 0);
+        assert_eq!(entries, [entry("1", true), entry("4", true)]);
+    }
+
+    #[test]
+    fn pruned_by_options() {
+        let now = unix_time_now();
+        let new = |query, favorite| HistoryEntry {
+            added: now,
+            ..entry(query, favorite)
+        };
+        let mut entries = vec![
+            entry("old", false),
+            entry("old favorite", true),
+            new("my secret", true),
+            new("new", false),
+        ];
+
+        let mut builder = GlobSetBuilder::new();
+        builder.add(Glob::new("*secret*").unwrap());
+        HistoryOptions {
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+            exclude: builder.build().unwrap(),
+            ..options()
+        }
+        .prune(&mut entries);
+
+        assert_eq!(entries, [entry("old favorite", true), new("new", false)]);
+    }
+
+    #[tokio::test]
+    async fn saved_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+
+        let history = History::load(Some(path.clone()), options()).await;
+        history.add("1+1", "1 + 1 = 2", "2").await.unwrap();
+        history.add("1+1", "1 + 1 = 2", "2").await.unwrap();
+        history.add("2+2", "2 + 2 = 4", "4").await.unwrap();
+        history.add("3+3", "3 + 3 = 6", "6").await.unwrap();
+        let [_, two, one] = &history.entries()[..] else {
+            panic!("expected 3 entries");
+        };
+        history.toggle_favorite(one).await.unwrap();
+        history.delete(two).await.unwrap();
+
+        let history = History::load(Some(path.clone()), options()).await;
+        assert_eq!(
+            queries(&history),
+            [("3+3".to_string(), false), ("1+1".to_string(), true)]
+        );
+
+        history.clear().await.unwrap();
+        let history = History::load(Some(path), options()).await;
+        assert_eq!(queries(&history), [("1+1".to_string(), true)]);
+        assert!(!dir.path().join("history.json.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn answers() {
+        let history = History::load(None, options()).await;
+        history.add("1+1", "1 + 1 = 2", "2").await.unwrap();
+        history
+            .add("5 m to ft", "5 m ≈ 16.40419948 ft", "16.40419948 ft")
+            .await
+            .unwrap();
+
+        assert_eq!(history.bind_answers("ans * 2"), "(16.40419948 ft) * 2");
+        assert_eq!(history.bind_answers("ans1+ans2"), "(16.40419948 ft)+(2)");
+        assert_eq!(
+            history.bind_answers("ans3 + ans02 + answer + fans"),
+            "ans3 + ans02 + answer + fans"
+        );
+    }
+
+    #[tokio::test]
+    async fn kept_in_memory() {
+        let history = History::load(None, options()).await;
+        history.add("1+1", "1 + 1 = 2", "2").await.unwrap();
+        assert_eq!(queries(&history), [("1+1".to_string(), false)]);
+    }
+
+    #[tokio::test]
+    async fn migrates_unversioned_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+        std::fs::write(
+            &path,
+            r#"[{"query":"1+1","equation":"1 + 1 = 2","result":"2"}]"#,
+        )
+        .unwrap();
+
+        let history = History::load(Some(path.clone()), options()).await;
+        assert_eq!(queries(&history), [("1+1".to_string(), false)]);
+
+        history.add("2+2", "2 + 2 = 4", "4").await.unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with(r#"{"version":2,"#));
+    }
+
+    #[tokio::test]
+    async fn sets_aside_unreadable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+        let newer = r#"{"version":3,"entries":[]}"#;
+        std::fs::write(&path, newer).unwrap();
+
+        let history = History::load(Some(path.clone()), options()).await;
+        assert_eq!(queries(&history), []);
+        history.add("1+1", "1 + 1 = 2", "2").await.unwrap();
+
+        let backup = std::fs::read_to_string(dir.path().join("history.json.bak")).unwrap();
+        assert_eq!(backup, newer);
+        let history = History::load(Some(path), options()).await;
+        assert_eq!(queries(&history), [("1+1".to_string(), false)]);
+    }
+}
@@ -0,0 +1,3 @@
+//! Parts of the plugin that are also used by the benchmarks.
+
+pub mod session;
@@ -1,163 +1,574 @@
-use std::{
-    path::PathBuf,
-    process::Stdio,
-    sync::{Arc, RwLock},
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use covey_plugin::{
+    Input, List, ListItem, ListSection, Plugin, Result, clone_async,
+    rank::{self, Weights},
+};
+use datetime::Format;
+use definitions::{Definition, Definitions};
+use exrates::{ExchangeRates, UpdatePolicy};
+use history::{History, HistoryEntry, HistoryOptions};
+use jiff::Zoned;
+use qalc::session::{self, Evaluation, Message, MessageKind, Session};
+use representation::Representation;
+use tokio::{
+    sync::{Mutex, Notify},
+    time,
 };
 
-use covey_plugin::{Input, List, ListItem, ListSection, Plugin, Result, clone_async, spawn};
-use serde::{Deserialize, Serialize};
-use tokio::process::Command;
+mod builtin;
+mod datetime;
+mod definitions;
+mod exrates;
+mod history;
+mod representation;
+mod storage;
 
 covey_plugin::include_manifest!();
 
-#[derive(Debug, Serialize, Deserialize)]
-struct HistoryEntry {
-    query: String,
-    equation: String,
-    result: String,
-}
+/// Query prefix for searching the history.
+const HISTORY_QUERY: &str = "history:";
 
-#[derive(Clone, Default)]
+/// Query prefix for listing variables and functions that were defined, so
+/// that they can be deleted.
+const DEFINITIONS_QUERY: &str = "definitions:";
+
+#[derive(Clone)]
 struct Qalc {
-    history: Arc<RwLock<Vec<HistoryEntry>>>,
+    history: History,
+    definitions: Definitions,
+    /// `None` if qalc isn't installed, so everything is evaluated by the
+    /// built-in calculator.
+    backend: Option<Backend>,
+    /// Whether to use the built-in calculator for simple arithmetic.
+    fast_path: bool,
+    /// How long to wait for a result.
+    timeout: Duration,
+    /// Notified when a new query starts, to stop evaluating older ones.
+    new_query: Arc<Notify>,
+}
+
+/// qalc and what depends on it.
+#[derive(Clone)]
+struct Backend {
     qalc_path: PathBuf,
+    session: Arc<Mutex<Session>>,
+    exchange_rates: ExchangeRates,
+}
+
+/// What evaluated a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Qalc,
+    Builtin,
 }
 
 impl Plugin for Qalc {
     type Config = Config;
 
-    async fn new(Config { qalc_path }: Config) -> Result<Self> {
-        let qalc_path = which::which(qalc_path)?;
-        eprintln!("resolved qalc path as {qalc_path:?}");
+    async fn new(config: Config) -> Result<Self> {
+        let definitions =
+            Definitions::load(covey_plugin::plugin_data_dir().join("definitions.json")).await;
 
-        // update exchange rates
-        spawn::command(&qalc_path, ["--exrates", "--", ""])?;
+        let backend = match which::which(&config.qalc_path) {
+            Ok(qalc_path) => {
+                eprintln!("resolved qalc path as {qalc_path:?}");
+                let exchange_rates = ExchangeRates::load(
+                    qalc_path.clone(),
+                    covey_plugin::plugin_data_dir().join("exchange-rates.json"),
+                    UpdatePolicy::from_config(&config),
+                )
+                .await;
+                let mut session = Session::new(qalc_path.clone());
+                session.set_startup_commands(definitions.commands());
 
-        let history = try_read_history().await.unwrap_or_default();
-        Ok(Self {
-            history: Arc::new(RwLock::new(history)),
-            qalc_path,
-        })
+                Some(Backend {
+                    qalc_path,
+                    session: Arc::new(Mutex::new(session)),
+                    exchange_rates,
+                })
+            }
+            Err(e) => {
+                eprintln!("qalc not found, using the built-in calculator: {e}");
+                None
+            }
+        };
+
+        let this = Self {
+            history: History::load(
+                config
+                    .save_history
+                    .then(|| covey_plugin::plugin_data_dir().join("history.json")),
+                HistoryOptions::from_config(&config)?,
+            )
+            .await,
+            definitions,
+            backend,
+            fast_path: config.fast_path,
+            timeout: Duration::from_millis(config.timeout.unsigned_abs().into()),
+            new_query: Arc::new(Notify::new()),
+        };
+        this.update_exchange_rates(true);
+        Ok(this)
     }
 
     async fn query(&self, query: String) -> Result<List> {
-        let output = self.get_qalc_output(&query, &[]).await?;
-        let equation = output.lines().last().unwrap_or_default().to_string();
-        let terse = self.get_qalc_output(&query, &["-t"]).await?;
+        self.new_query.notify_waiters();
+        if let Some(rest) = query.strip_prefix(HISTORY_QUERY) {
+            return Ok(self.search_history(rest.trim_start(), &query).await);
+        }
+        if let Some(rest) = query.strip_prefix(DEFINITIONS_QUERY) {
+            return Ok(self.search_definitions(rest.trim_start(), &query).await);
+        }
+        if let Some(definition) = Definition::parse(&query) {
+            return Ok(List::new(vec![self.define_item(definition)]));
+        }
+
+        let now = Zoned::now();
+        let mut date_time = datetime::evaluate(&query, &now);
+        let newer_query = self.new_query.notified();
+        tokio::pin!(newer_query);
+
+        let evaluation = time::timeout(self.timeout, self.evaluate_query(&query));
+        let evaluation = tokio::select! {
+            evaluation = evaluation => evaluation,
+            // the newer query's list replaces this one anyways
+            () = &mut newer_query => return Ok(List::new(Vec::new())),
+        };
+
+        let items = match evaluation {
+            Ok(evaluation) => {
+                let (engine, mut evaluation) = evaluation?;
+                let failed = evaluation.failed();
+                let representations = if failed || is_command(&query) {
+                    Vec::new()
+                } else {
+                    tokio::select! {
+                        representations = self.representations(engine, &evaluation) => representations,
+                        () = &mut newer_query => return Ok(List::new(Vec::new())),
+                    }
+                };
+                if failed && date_time.is_some() {
+                    // qalc doesn't understand most date queries, which
+                    // isn't worth showing
+                    evaluation.messages.clear();
+                }
+                if date_time.is_none() && !failed {
+                    // like a date from `today + 3 days`
+                    date_time = datetime::evaluate(&evaluation.terse, &now);
+                }
+
+                let mut items: Vec<_> = evaluation.messages.drain(..).map(message_item).collect();
+                if !failed {
+                    let has_currency = exrates::mentions_currency(&evaluation.equation);
+                    let item = self.result_item(&query, evaluation);
+                    let item = match (engine, &self.backend) {
+                        (Engine::Builtin, _) => {
+                            item.with_description(self.builtin_description(None))
+                        }
+                        (Engine::Qalc, Some(backend)) if has_currency => {
+                            self.update_exchange_rates(false);
+                            item.with_description(backend.exchange_rates.description())
+                        }
+                        (Engine::Qalc, _) => item,
+                    };
+                    items.push(item);
+                    items.extend(representations.into_iter().map(
+                        |(representation, evaluation)| {
+                            let item = self.result_item(&query, evaluation);
+                            match engine {
+                                Engine::Qalc => item.with_description(representation.name()),
+                                Engine::Builtin => item.with_description(
+                                    self.builtin_description(Some(representation)),
+                                ),
+                            }
+                        },
+                    ));
+                }
+                items
+            }
+            Err(_) => vec![
+                ListItem::new("Timed out")
+                    .with_description(format!(
+                        "qalc took longer than {} ms, so it was stopped",
+                        self.timeout.as_millis()
+                    ))
+                    .with_icon_name("dialog-warning"),
+            ],
+        };
+
+        let history = self
+            .history
+            .entries()
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| self.history_item(entry, i + 1, &query, &query))
+            .collect();
 
-        let item = ListItem::new(output)
+        let date_time = date_time
+            .into_iter()
+            .flatten()
+            .map(|format| self.date_time_item(&query, format))
+            .collect();
+
+        Ok(List::from_sections(vec![
+            ListSection::new("Date and time", date_time),
+            ListSection::unnamed(items),
+            ListSection::new("History", history),
+        ]))
+    }
+}
+
+impl Qalc {
+    fn result_item(
+        &self,
+        query: &str,
+        Evaluation {
+            output,
+            equation,
+            terse,
+            ..
+        }: Evaluation,
+    ) -> ListItem {
+        ListItem::new(output)
             .with_icon_name("qalculate")
+            // the result is copied even if it can't be saved to the history,
+            // and the menu is left open to show the error.
             .on_copy(clone_async!(this = self, query, equation, terse, |menu| {
-                this.add_to_history(&query, equation, &terse);
+                menu.copy(&terse);
+                this.history.add(&query, equation, terse).await?;
                 menu.close();
-                menu.copy(terse);
                 menu.set_input(Input::new(query));
                 Ok(())
             }))
             .on_copy_equation(clone_async!(this = self, query, equation, terse, |menu| {
-                this.add_to_history(&query, &equation, terse);
+                menu.copy(&equation);
+                this.history.add(&query, equation, terse).await?;
                 menu.close();
-                menu.copy(equation);
                 menu.set_input(Input::new(query));
                 Ok(())
             }))
             .on_complete(clone_async!(this = self, query, equation, terse, |menu| {
-                this.add_to_history(&query, equation, &terse);
+                this.history.add(&query, equation, &terse).await?;
                 menu.set_input(Input::new(terse));
                 Ok(())
-            }));
-
-        // add history items
-        let history = self.history.read().unwrap();
-        let history = history
-            .iter()
-            .rev()
-            .map(
-                |HistoryEntry {
-                     query: history_query,
-                     equation,
-                     result,
-                 }| {
-                    ListItem::new(equation)
-                        .on_append_history_result(clone_async!(query, result, |menu| {
-                            menu.set_input(Input::new(format!("{query}{result}")));
-                            Ok(())
-                        }))
-                        .on_insert_history_query(clone_async!(history_query, |menu| {
-                            menu.set_input(Input::new(history_query));
-                            Ok(())
-                        }))
-                },
-            )
-            .collect();
+            }))
+    }
 
-        Ok(List::from_sections(vec![
-            ListSection::unnamed(vec![item]),
-            ListSection::new("History", history),
-        ]))
+    /// An item for one format of a date or time, which is copied like a
+    /// result.
+    fn date_time_item(&self, query: &str, Format { name, text }: Format) -> ListItem {
+        let evaluation = Evaluation {
+            output: text.clone(),
+            equation: format!("{query} = {text}"),
+            terse: text,
+            messages: Vec::new(),
+        };
+        self.result_item(query, evaluation)
+            .with_description(name)
+            .with_icon_name("x-office-calendar")
     }
-}
 
-impl Qalc {
-    pub fn add_to_history(
+    /// Downloads new exchange rates in the background if they are due, and
+    /// then restarts qalc to use them.
+    fn update_exchange_rates(&self, starting: bool) {
+        let Some(backend) = self.backend.clone() else {
+            return;
+        };
+        tokio::spawn(async move {
+            match backend.exchange_rates.update_if_needed(starting).await {
+                Ok(true) => backend.session.lock().await.stop(),
+                Ok(false) => {}
+                // probably offline, so the old rates are used
+                Err(e) => eprintln!("failed to update exchange rates: {e:#}"),
+            }
+        });
+    }
+
+    /// Lists favorites and then the rest of the history, or ranks all of it
+    /// if there is a search.
+    async fn search_history(&self, search: &str, query: &str) -> List {
+        let entries: Vec<_> = self.history.entries().into_iter().enumerate().collect();
+        // the history query would be in the way of appending results
+        let item = |(i, entry)| self.history_item(entry, i + 1, "", query);
+
+        if !search.is_empty() {
+            let items: Vec<_> = entries.into_iter().map(item).collect();
+            return List::new(rank::rank(search, &items, Weights::without_history()).await);
+        }
+
+        let (favorites, rest): (Vec<_>, Vec<_>) =
+            entries.into_iter().partition(|(_, entry)| entry.favorite);
+        List::from_sections(vec![
+            ListSection::new("Favorites", favorites.into_iter().map(item).collect()),
+            ListSection::new("History", rest.into_iter().map(item).collect()),
+        ])
+    }
+
+    /// An item for a history entry.
+    ///
+    /// `answer` is the number that the entry's result can be used as, like
+    /// `ans2`. `input` is what the result is appended to, and `query` is set
+    /// again after changing the history to show the change.
+    fn history_item(
         &self,
+        entry: HistoryEntry,
+        answer: usize,
+        input: &str,
         query: &str,
-        equation: impl Into<String>,
-        result: impl Into<String>,
-    ) {
-        // only add to history if the query changed
-        let mut history = self.history.write().unwrap();
-        if history.last().is_none_or(|last| last.query != query) {
-            let entry = HistoryEntry {
-                query: query.to_string(),
-                equation: equation.into(),
-                result: result.into(),
+    ) -> ListItem {
+        let history = &self.history;
+        let HistoryEntry {
+            query: history_query,
+            equation,
+            result,
+            favorite,
+            ..
+        } = entry.clone();
+
+        let item =
+            ListItem::new(equation).with_description(format!("ans{answer} · {history_query}"));
+        let item = if favorite {
+            item.with_icon_name("starred")
+        } else {
+            item
+        };
+        item.on_append_history_result(clone_async!(input, result, |menu| {
+            menu.set_input(Input::new(format!("{input}{result}")));
+            Ok(())
+        }))
+        .on_insert_history_query(clone_async!(history_query, |menu| {
+            menu.set_input(Input::new(history_query));
+            Ok(())
+        }))
+        .on_toggle_favorite(clone_async!(history, entry, query, |menu| {
+            history.toggle_favorite(&entry).await?;
+            menu.set_input(Input::new(query));
+            Ok(())
+        }))
+        .on_delete_history_entry(clone_async!(history, entry, query, |menu| {
+            history.delete(&entry).await?;
+            menu.set_input(Input::new(query));
+            Ok(())
+        }))
+        .on_clear_history(clone_async!(history, query, |menu| {
+            history.clear().await?;
+            menu.set_input(Input::new(query));
+            Ok(())
+        }))
+    }
+
+    /// An item that saves a new definition.
+    fn define_item(&self, definition: Definition) -> ListItem {
+        let name = definition.name.clone();
+        ListItem::new(definition.to_string())
+            .with_description(match definition.parameters {
+                Some(_) => "Define function",
+                None => "Define variable",
+            })
+            .with_icon_name("qalculate")
+            .on_define(clone_async!(this = self, definition, name, |menu| {
+                this.definitions.define(definition).await?;
+                this.reload_definitions().await;
+                menu.set_input(Input::new(name));
+                Ok(())
+            }))
+    }
+
+    async fn search_definitions(&self, search: &str, query: &str) -> List {
+        let items: Vec<_> = self
+            .definitions
+            .list()
+            .into_iter()
+            .map(|definition| {
+                let name = definition.name.clone();
+                ListItem::new(definition.to_string()).on_delete_definition(clone_async!(
+                    this = self,
+                    name,
+                    query,
+                    |menu| {
+                        this.definitions.delete(&name).await?;
+                        this.reload_definitions().await;
+                        menu.set_input(Input::new(query));
+                        Ok(())
+                    }
+                ))
+            })
+            .collect();
+
+        List::new(rank::rank(search, &items, Weights::without_history()).await)
+    }
+
+    /// Makes qalc use the current definitions.
+    async fn reload_definitions(&self) {
+        if let Some(backend) = &self.backend {
+            backend
+                .session
+                .lock()
+                .await
+                .set_startup_commands(self.definitions.commands());
+        }
+    }
+
+    /// Describes a result from the built-in calculator, so that it isn't
+    /// mistaken for qalc's.
+    fn builtin_description(&self, representation: Option<Representation>) -> String {
+        let engine = match self.backend {
+            Some(_) => "Built-in calculator",
+            None => "Built-in calculator (qalc isn't installed)",
+        };
+        match representation {
+            Some(representation) => format!("{} · {engine}", representation.name()),
+            None => engine.to_string(),
+        }
+    }
+
+    /// Evaluates the query, with the built-in calculator if it's enough.
+    async fn evaluate_query(&self, query: &str) -> Result<(Engine, Evaluation)> {
+        let expression = self.history.bind_answers(query);
+        // definitions are only known to qalc
+        let use_builtin = self.fast_path
+            && builtin::is_trivial(&expression)
+            && self.definitions.first_used_in(&expression).is_none();
+        let engine = match &self.backend {
+            Some(_) if !use_builtin => Engine::Qalc,
+            _ => Engine::Builtin,
+        };
+
+        let evaluation = self.evaluate(engine, &expression).await?;
+        Ok((engine, evaluation))
+    }
+
+    /// The other representations of a result that are different to it,
+    /// converted from the result by the engine that evaluated it.
+    ///
+    /// The conversions share one timeout, and the ones that fail or don't
+    /// finish in time are left out.
+    async fn representations(
+        &self,
+        engine: Engine,
+        evaluation: &Evaluation,
+    ) -> Vec<(Representation, Evaluation)> {
+        // a fraction of a rounded result isn't the exact one, which qalc
+        // already shows in the equation, like `10 / 3 = 3 + 1/3 ≈ 3.333333333`
+        let approximate = evaluation.equation.contains(" ≈ ");
+        let deadline = time::Instant::now() + self.timeout;
+
+        let mut representations: Vec<(Representation, Evaluation)> = Vec::new();
+        for representation in Representation::applicable_to(&evaluation.terse) {
+            if approximate && representation == Representation::Fraction {
+                continue;
+            }
+            let conversion = representation.convert(&evaluation.terse);
+            let conversion = time::timeout_at(deadline, self.evaluate(engine, &conversion));
+            let Ok(Ok(converted)) = conversion.await else {
+                continue;
             };
-            history.push(entry);
-
-            // only keep up to 500 items
-            let len = history.len();
-            history.drain(..len.saturating_sub(500));
-
-            let json = serde_json::to_string(&*history).expect("serialization should not fail");
-            tokio::spawn(async move {
-                tokio::fs::write(history_file_path(), json)
-                    .await
-                    .expect("(TODO) oops failed to write to file");
-            });
+            let is_new = converted.terse != evaluation.terse
+                && representations
+                    .iter()
+                    .all(|(_, other)| converted.terse != other.terse);
+            if !converted.failed() && is_new {
+                representations.push((representation, converted));
+            }
         }
+        representations
     }
 
-    async fn get_qalc_output(&self, query: &str, extra_args: &[&str]) -> Result<String> {
-        // warnings start with "warning:".
-        // errors start with "error:".
-        // also will have non-zero exit code if there are errors, including in --terse
-        // mode which doesn't show any "error:" lines.
-        let output = Command::new(&self.qalc_path)
-            .args(["--defaults", "--color=0", "-set", "upxrates 0"])
-            .args(extra_args)
-            .arg("--")
-            .arg(query)
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null()) // nothing is put into stderr anyways
-            .spawn()?
-            .wait_with_output()
-            .await?;
-
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    /// Evaluates an expression that answers have already been bound in.
+    async fn evaluate(&self, engine: Engine, expression: &str) -> Result<Evaluation> {
+        let backend = match (engine, &self.backend) {
+            (Engine::Qalc, Some(backend)) => backend,
+            _ => return Ok(self.evaluate_builtin(expression)),
+        };
+        if !is_command(expression) {
+            return backend.session.lock().await.evaluate(expression).await;
+        }
+
+        // commands would change the session for later queries, so run them
+        // on their own like any other expression.
+        session::evaluate_once(&backend.qalc_path, &self.definitions.commands(), expression).await
+    }
+
+    /// Evaluates an expression with the built-in calculator, which can't use
+    /// any definitions.
+    fn evaluate_builtin(&self, expression: &str) -> Evaluation {
+        let Some(name) = self.definitions.first_used_in(expression) else {
+            return builtin::evaluate(expression);
+        };
+        Evaluation {
+            output: String::new(),
+            equation: String::new(),
+            terse: String::new(),
+            messages: vec![Message {
+                kind: MessageKind::Error,
+                text: format!("{name} is defined for qalc, which isn't installed"),
+            }],
+        }
     }
 }
 
-fn history_file_path() -> std::path::PathBuf {
-    covey_plugin::plugin_data_dir().join("history.json")
+/// An item for a warning or error, which can't be copied.
+fn message_item(Message { kind, text }: Message) -> ListItem {
+    let (description, icon) = match kind {
+        MessageKind::Warning => ("Warning", "dialog-warning"),
+        MessageKind::Error => ("Error", "dialog-error"),
+    };
+    ListItem::new(text)
+        .with_description(description)
+        .with_icon_name(icon)
 }
 
-async fn try_read_history() -> std::io::Result<Vec<HistoryEntry>> {
-    let entries: Vec<HistoryEntry> =
-        serde_json::from_slice(&tokio::fs::read(history_file_path()).await?)?;
+/// Whether the query starts with one of qalc's interactive mode commands,
+/// like `set precision 5` or `quit`.
+fn is_command(query: &str) -> bool {
+    const COMMANDS: &[&str] = &[
+        "approximate",
+        "assume",
+        "base",
+        "clear",
+        "convert",
+        "copy",
+        "delete",
+        "exact",
+        "exit",
+        "exrates",
+        "expand",
+        "factor",
+        "find",
+        "function",
+        "help",
+        "info",
+        "keep",
+        "list",
+        "mode",
+        "partial",
+        "pop",
+        "quit",
+        "rotate",
+        "rpn",
+        "save",
+        "set",
+        "simplify",
+        "stack",
+        "store",
+        "swap",
+        "to",
+        "unkeep",
+        "unset",
+        "variable",
+    ];
 
-    Ok(entries)
+    let query = query.trim_start();
+    let first_word = query
+        .strip_prefix('/')
+        .unwrap_or(query)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    COMMANDS
+        .iter()
+        .any(|command| command.eq_ignore_ascii_case(first_word))
 }
 
 fn main() {
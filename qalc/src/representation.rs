@@ -0,0 +1,117 @@
+//! Other forms of a result, like hexadecimal or a fraction.
+
+/// A form that a result can be converted to with qalc's `to` operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Hexadecimal,
+    Octal,
+    Binary,
+    Fraction,
+    Scientific,
+    /// Converted to SI base units.
+    BaseUnits,
+    /// Converted to the units that qalc thinks fit best.
+    OptimalUnits,
+}
+
+impl Representation {
+    /// The representations that are worth showing for a result, from its
+    /// terse output.
+    ///
+    /// Bases only make sense for integers and fractions only for other
+    /// numbers that aren't too large or small. Anything that isn't a number
+    /// might have units.
+    pub fn applicable_to(terse: &str) -> Vec<Self> {
+        // negative numbers are printed with a unicode minus sign
+        let number = terse.replace('−', "-");
+
+        if number.parse::<i128>().is_ok() {
+            let mut representations = vec![Self::Hexadecimal, Self::Octal, Self::Binary];
+            if is_large_or_small(&number) {
+                representations.push(Self::Scientific);
+            }
+            representations
+        } else if number.parse::<f64>().is_ok_and(f64::is_finite) {
+            if is_scientific(&number) {
+                Vec::new()
+            } else if is_large_or_small(&number) {
+                vec![Self::Fraction, Self::Scientific]
+            } else {
+                vec![Self::Fraction]
+            }
+        } else {
+            vec![Self::BaseUnits, Self::OptimalUnits]
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hexadecimal => "Hexadecimal",
+            Self::Octal => "Octal",
+            Self::Binary => "Binary",
+            Self::Fraction => "Fraction",
+            Self::Scientific => "Scientific notation",
+            Self::BaseUnits => "Base units",
+            Self::OptimalUnits => "Optimal units",
+        }
+    }
+
+    /// An expression that converts a result, like `255`, to this
+    /// representation.
+    pub fn convert(self, result: &str) -> String {
+        let target = match self {
+            Self::Hexadecimal => "hex",
+            Self::Octal => "oct",
+            Self::Binary => "bin",
+            Self::Fraction => "fraction",
+            Self::Scientific => "sci",
+            Self::BaseUnits => "base",
+            Self::OptimalUnits => "optimal",
+        };
+        format!("{result} to {target}")
+    }
+}
+
+fn is_scientific(number: &str) -> bool {
+    number.contains(['E', 'e'])
+}
+
+/// Whether a number is easier to read in scientific notation. Numbers that
+/// are already in scientific notation aren't.
+fn is_large_or_small(number: &str) -> bool {
+    if is_scientific(number) {
+        return false;
+    }
+    let Ok(number) = number.parse::<f64>() else {
+        return false;
+    };
+    let magnitude = number.abs();
+    magnitude >= 1e6 || (magnitude != 0.0 && magnitude < 1e-3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Representation::*, *};
+
+    #[test]
+    fn applicable_representations() {
+        assert_eq!(
+            Representation::applicable_to("255"),
+            [Hexadecimal, Octal, Binary]
+        );
+        assert_eq!(
+            Representation::applicable_to("−12345678"),
+            [Hexadecimal, Octal, Binary, Scientific]
+        );
+        assert_eq!(Representation::applicable_to("0.75"), [Fraction]);
+        assert_eq!(
+            Representation::applicable_to("0.0001234"),
+            [Fraction, Scientific]
+        );
+        assert_eq!(Representation::applicable_to("2.652528598E32"), []);
+        assert_eq!(
+            Representation::applicable_to("16.40419948 ft"),
+            [BaseUnits, OptimalUnits]
+        );
+    }
+}
@@ -0,0 +1,535 @@
+//! Evaluating expressions with a qalc process that keeps running between
+//! queries, so that it only loads it's definitions once.
+
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+};
+
+use covey_plugin::{
+    Result,
+    anyhow::{Context, bail},
+};
+use tempfile::NamedTempFile;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+};
+
+/// Options that every qalc process is started with.
+const QALC_ARGS: [&str; 4] = ["--defaults", "--color=0", "-set", "upxrates 0"];
+
+/// Evaluated after each expression. It's output marks the end of the
+/// expression's output.
+const END_MARKER: &str = "covey-qalc-end-of-output";
+
+/// What qalc printed for an expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    /// The output without any warnings or errors.
+    pub output: String,
+    /// The last line of the output, like `1 + 1 = 2`.
+    pub equation: String,
+    /// Only the result, like `2`.
+    pub terse: String,
+    /// Warnings and errors, in the order they were printed.
+    pub messages: Vec<Message>,
+}
+
+/// A line starting with `warning:` or `error:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Warning,
+    Error,
+}
+
+impl Evaluation {
+    pub fn from_output(output: &str) -> Self {
+        let mut messages = Vec::new();
+        let mut lines = Vec::new();
+        for line in output.lines() {
+            match Message::parse(line) {
+                Some(message) => messages.push(message),
+                None => lines.push(line),
+            }
+        }
+
+        let equation = lines.last().copied().unwrap_or_default();
+        Self {
+            output: lines.join("\n"),
+            equation: equation.to_string(),
+            terse: result_of(equation).to_string(),
+            messages,
+        }
+    }
+
+    /// Whether there was an error, which means that the result is missing
+    /// or wrong.
+    pub fn failed(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|message| message.kind == MessageKind::Error)
+    }
+}
+
+impl Message {
+    fn parse(line: &str) -> Option<Self> {
+        if let Some(text) = line.strip_prefix("error:") {
+            Some(Self::new(MessageKind::Error, text))
+        } else {
+            let text = line.strip_prefix("warning:")?;
+            Some(Self::new(MessageKind::Warning, text))
+        }
+    }
+
+    fn new(kind: MessageKind, text: &str) -> Self {
+        Self {
+            kind,
+            text: text.trim().to_string(),
+        }
+    }
+}
+
+/// Gets the result from an equation like `1 + 1 = 2` or
+/// `√(2) ≈ 1.414213562`, which comes after the last `=` or `≈`.
+fn result_of(equation: &str) -> &str {
+    [" = ", " ≈ "]
+        .into_iter()
+        .filter_map(|separator| {
+            let index = equation.rfind(separator)?;
+            Some(&equation[index + separator.len()..])
+        })
+        // the shortest is after the last separator
+        .min_by_key(|result| result.len())
+        .unwrap_or(equation)
+}
+
+/// A qalc process in interactive mode, reading expressions from stdin.
+///
+/// The process is started when it's first needed, and started again if it
+/// exits.
+pub struct Session {
+    qalc_path: PathBuf,
+    /// Run whenever qalc starts, like definitions of variables.
+    startup_commands: Vec<String>,
+    process: Option<Process>,
+}
+
+struct Process {
+    /// Killed when dropped.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Process {
+    fn spawn(qalc_path: &Path) -> Result<Self> {
+        let mut child = Command::new(qalc_path)
+            .args(QALC_ARGS)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null()) // nothing is put into stderr anyways
+            .kill_on_drop(true)
+            .spawn()
+            .context("failed to start qalc")?;
+
+        Ok(Self {
+            stdin: child.stdin.take().context("missing qalc stdin")?,
+            stdout: BufReader::new(child.stdout.take().context("missing qalc stdout")?).lines(),
+            _child: child,
+        })
+    }
+
+    async fn evaluate(&mut self, expression: &str) -> Result<String> {
+        self.stdin
+            .write_all(format!("{expression}\n\"{END_MARKER}\"\n").as_bytes())
+            .await?;
+        self.stdin.flush().await?;
+
+        let mut output = Vec::new();
+        loop {
+            let line = self.stdout.next_line().await?.context("qalc exited")?;
+            if line.contains(END_MARKER) {
+                break;
+            }
+
+            // results are surrounded by blank lines and indented, and a
+            // prompt is printed before reading each line.
+            let line = line.trim_start_matches("> ").trim();
+            if !line.is_empty() {
+                output.push(line.to_string());
+            }
+        }
+
+        Ok(output.join("\n"))
+    }
+}
+
+impl Session {
+    pub fn new(qalc_path: PathBuf) -> Self {
+        Self {
+            qalc_path,
+            startup_commands: Vec::new(),
+            process: None,
+        }
+    }
+
+    /// Replaces the commands that are run whenever qalc starts, and restarts
+    /// it to run them.
+    pub fn set_startup_commands(&mut self, commands: Vec<String>) {
+        self.startup_commands = commands;
+        self.stop();
+    }
+
+    /// Evaluates an expression, restarting qalc once if it has exited.
+    ///
+    /// If this is cancelled, qalc is stopped and started again for the next
+    /// expression.
+    pub async fn evaluate(&mut self, expression: &str) -> Result<Evaluation> {
+        // each line is a separate expression
+        let expression = expression.replace(['\n', '\r'], " ");
+        if depends_on_state(&expression) {
+            return evaluate_once(&self.qalc_path, &self.startup_commands, &expression).await;
+        }
+
+        let output = match self.try_evaluate(&expression).await {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("restarting qalc: {e:#}");
+                self.try_evaluate(&expression).await?
+            }
+        };
+
+        Ok(Evaluation::from_output(&output))
+    }
+
+    /// Stops qalc, so that it's started again for the next expression. This
+    /// makes it load any definitions that changed, like exchange rates.
+    pub fn stop(&mut self) {
+        self.process = None;
+    }
+
+    async fn try_evaluate(&mut self, expression: &str) -> Result<String> {
+        // the process is only put back after all of the output is read, as
+        // otherwise the rest of it would be read for the next expression.
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => self.start().await?,
+        };
+        let output = process.evaluate(expression).await?;
+        self.process = Some(process);
+        Ok(output)
+    }
+
+    async fn start(&self) -> Result<Process> {
+        let mut process = Process::spawn(&self.qalc_path)?;
+        for command in &self.startup_commands {
+            // a broken definition shouldn't stop everything else from working
+            let output = process.evaluate(command).await?;
+            if !output.is_empty() {
+                eprintln!("output from {command:?}: {output}");
+            }
+        }
+        Ok(process)
+    }
+}
+
+/// Whether an expression would see or change what earlier expressions in
+/// the session did, so that its result would depend on what was typed
+/// before it.
+///
+/// This is qalc's previous answers, which are also changed by the end
+/// markers, and assignments like `x = 5` or `x := 5`, which would be kept
+/// for later expressions.
+fn depends_on_state(expression: &str) -> bool {
+    expression.contains('=')
+        || expression
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .any(|word| {
+                word == "answer"
+                    || word
+                        .strip_prefix("ans")
+                        .is_some_and(|number| number.chars().all(|c| c.is_ascii_digit()))
+            })
+}
+
+/// Evaluates an expression with new qalc processes, one for the output and
+/// one for the terse result, which each run `startup_commands` first.
+pub async fn evaluate_once(
+    qalc_path: &Path,
+    startup_commands: &[String],
+    expression: &str,
+) -> Result<Evaluation> {
+    let mut args = Vec::new();
+    // qalc runs the commands in a file before evaluating the expression
+    let commands_file = if startup_commands.is_empty() {
+        None
+    } else {
+        let mut file = NamedTempFile::new().context("failed to write qalc commands")?;
+        writeln!(file, "{}", startup_commands.join("\n"))?;
+        Some(file.into_temp_path())
+    };
+    if let Some(commands_file) = &commands_file {
+        args.extend(["-f".as_ref(), commands_file.as_os_str()]);
+    }
+
+    let output = run_once(qalc_path, expression, &args).await?;
+    args.push("-t".as_ref());
+    let terse = run_once(qalc_path, expression, &args).await?;
+
+    let mut evaluation = Evaluation {
+        terse: String::from_utf8(terse.stdout)?.trim().to_string(),
+        ..Evaluation::from_output(String::from_utf8(output.stdout)?.trim())
+    };
+    // qalc prints its messages to stdout, but anything in stderr is shown
+    // too instead of being lost.
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        let line = line.trim();
+        if !line.is_empty() {
+            let message =
+                Message::parse(line).unwrap_or_else(|| Message::new(MessageKind::Error, line));
+            evaluation.messages.push(message);
+        }
+    }
+    // qalc also exits with an error status for errors, which is all there is
+    // for some of them.
+    if !output.status.success() && !evaluation.failed() {
+        evaluation.messages.push(Message::new(
+            MessageKind::Error,
+            &format!("qalc failed with {}", output.status),
+        ));
+    }
+    Ok(evaluation)
+}
+
+/// Downloads new exchange rates for qalc.
+pub async fn update_exchange_rates(qalc_path: &Path) -> Result<()> {
+    let output = run_once(qalc_path, "", &["--exrates".as_ref()]).await?;
+    let evaluation = Evaluation::from_output(&String::from_utf8_lossy(&output.stdout));
+    if let Some(error) = evaluation
+        .messages
+        .iter()
+        .find(|message| message.kind == MessageKind::Error)
+    {
+        bail!("{}", error.text);
+    }
+    if !output.status.success() {
+        bail!("qalc failed with {}", output.status);
+    }
+    Ok(())
+}
+
+async fn run_once(qalc_path: &Path, expression: &str, extra_args: &[&OsStr]) -> Result<Output> {
+    let output = Command::new(qalc_path)
+        .args(QALC_ARGS)
+        .args(extra_args)
+        .arg("--")
+        .arg(expression)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?
+        .wait_with_output()
+        .await?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, time::Duration};
+
+    use super::*;
+
+    /// A qalc that knows a few expressions, formatted like in interactive
+    /// mode.
+    fn fake_qalc(dir: &Path) -> PathBuf {
+        let path = dir.join("qalc");
+        let script = r#"#!/bin/sh
+[ "$1 $2 $3 $4" = "--defaults --color=0 -set upxrates 0" ] || exit 1
+shift 4
+if [ "$#" -gt 0 ]; then
+    # run once, with [-f file] [-t] -- expression
+    if [ "$1" = -f ]; then
+        grep -qx 'variable two 2' "$2" && defined=1
+        shift 2
+    fi
+    [ "$1" = -t ] && terse=1 && shift
+    [ "$1" = -- ] || exit 1
+    case "$2" in
+        ans) if [ "$terse" ]; then echo 0; else echo 'ans = 0'; fi ;;
+        x) echo 'error: "x" is not defined'; exit 1 ;;
+        two) if [ ! "$defined" ]; then
+            echo 'error: "two" is not defined'
+            exit 1
+        elif [ "$terse" ]; then
+            echo 2
+        else
+            echo 'two = 2'
+        fi ;;
+        *) echo "$2" ;;
+    esac
+    exit 0
+fi
+ans=0
+while IFS= read -r line; do
+    case "$line" in
+        '"covey-qalc-end-of-output"')
+            printf '> \n  "covey-qalc-end-of-output"\n\n'
+            ans='"covey-qalc-end-of-output"' ;;
+        '1+1') printf '> \n  1 + 1 = 2\n\n'; ans=2 ;;
+        ans) printf '> \n  ans = %s\n\n' "$ans" ;;
+        'x = 5') printf '> \n  x = 5\n\n'; x=5 ;;
+        x) if [ "$x" ]; then
+            printf '> \n  x = 5\n\n'
+        else
+            printf '> error: "x" is not defined\n  x\n\n'
+        fi ;;
+        'sqrt 2') printf '> \n  sqrt(2) = √(2) ≈ 1.414213562\n\n' ;;
+        'variable two 2') defined=1 ;;
+        two) if [ "$defined" ]; then
+            printf '> \n  two = 2\n\n'
+        else
+            printf '> error: "two" is not defined\n  two\n\n'
+        fi ;;
+        'a b') printf '> warning: a is not defined\n  a × b = ab\n\n' ;;
+        '1/') printf '> error: Misplaced operator(s) "/" ignored\n  1 = 1\n\n' ;;
+        slow) sleep 10 ;;
+        exit) exit 0 ;;
+        *) printf '> \n  %s\n\n' "$line" ;;
+    esac
+done
+"#;
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn results_of_equations() {
+        assert_eq!(result_of("1 + 1 = 2"), "2");
+        assert_eq!(result_of("5 m ≈ 16.40419948 ft"), "16.40419948 ft");
+        assert_eq!(result_of("10 / 3 = 3 + 1/3 ≈ 3.333333333"), "3.333333333");
+        assert_eq!(result_of("4"), "4");
+    }
+
+    #[tokio::test]
+    async fn evaluates_in_one_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = Session::new(fake_qalc(dir.path()));
+
+        assert_eq!(
+            session.evaluate("1+1").await.unwrap(),
+            Evaluation {
+                output: "1 + 1 = 2".to_string(),
+                equation: "1 + 1 = 2".to_string(),
+                terse: "2".to_string(),
+                messages: Vec::new(),
+            }
+        );
+        assert_eq!(
+            session.evaluate("sqrt 2").await.unwrap().terse,
+            "1.414213562"
+        );
+        assert_eq!(
+            session.evaluate("a b").await.unwrap(),
+            Evaluation {
+                output: "a × b = ab".to_string(),
+                equation: "a × b = ab".to_string(),
+                terse: "ab".to_string(),
+                messages: vec![Message::new(MessageKind::Warning, "a is not defined")],
+            }
+        );
+
+        let error = session.evaluate("1/").await.unwrap();
+        assert!(error.failed());
+        assert_eq!(
+            error.messages,
+            [Message::new(
+                MessageKind::Error,
+                r#"Misplaced operator(s) "/" ignored"#
+            )]
+        );
+        assert_eq!(session.evaluate("").await.unwrap().output, "");
+    }
+
+    #[tokio::test]
+    async fn earlier_expressions_are_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = Session::new(fake_qalc(dir.path()));
+
+        // the end marker after 1+1 would be the answer in the session
+        assert_eq!(session.evaluate("1+1").await.unwrap().terse, "2");
+        assert_eq!(session.evaluate("ans").await.unwrap().terse, "0");
+
+        assert!(!session.evaluate("x = 5").await.unwrap().failed());
+        assert!(session.evaluate("x").await.unwrap().failed());
+    }
+
+    #[test]
+    fn expressions_depending_on_state() {
+        assert!(depends_on_state("ans * 2"));
+        assert!(depends_on_state("ans2+answer"));
+        assert!(depends_on_state("x := 5"));
+        assert!(!depends_on_state("answers + fans"));
+        assert!(!depends_on_state("5 m to ft"));
+    }
+
+    #[tokio::test]
+    async fn restarts_after_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = Session::new(fake_qalc(dir.path()));
+
+        // exits again after restarting
+        assert!(session.evaluate("exit").await.is_err());
+        assert_eq!(session.evaluate("1+1").await.unwrap().terse, "2");
+    }
+
+    #[tokio::test]
+    async fn runs_startup_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = Session::new(fake_qalc(dir.path()));
+
+        assert!(session.evaluate("two").await.unwrap().failed());
+        session.set_startup_commands(vec!["variable two 2".to_string()]);
+        assert_eq!(session.evaluate("two").await.unwrap().terse, "2");
+        // also after restarting
+        assert!(session.evaluate("exit").await.is_err());
+        assert_eq!(session.evaluate("two").await.unwrap().terse, "2");
+    }
+
+    #[tokio::test]
+    async fn evaluates_once_with_startup_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let qalc_path = fake_qalc(dir.path());
+
+        let missing = evaluate_once(&qalc_path, &[], "two").await.unwrap();
+        assert_eq!(
+            missing.messages,
+            [Message::new(MessageKind::Error, r#""two" is not defined"#)]
+        );
+        let defined = evaluate_once(&qalc_path, &["variable two 2".to_string()], "two")
+            .await
+            .unwrap();
+        assert_eq!(defined.equation, "two = 2");
+        assert_eq!(defined.terse, "2");
+    }
+
+    #[tokio::test]
+    async fn restarts_after_cancelling() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = Session::new(fake_qalc(dir.path()));
+
+        let slow = tokio::time::timeout(Duration::from_millis(100), session.evaluate("slow"));
+        assert!(slow.await.is_err());
+        assert_eq!(session.evaluate("1+1").await.unwrap().terse, "2");
+    }
+}
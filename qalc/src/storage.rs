@@ -0,0 +1,33 @@
+//! Files that the plugin saves in its data dir.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use covey_plugin::{Result, anyhow::Error};
+
+/// Writes `contents` to a temporary file and then replaces `path` with it,
+/// so that `path` is never partly written.
+pub async fn write_atomically(path: &Path, contents: Vec<u8>) -> Result<()> {
+    let temp_path = with_suffix(path, ".tmp");
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+/// Reports that the file at `path` couldn't be read, and moves it to
+/// `<path>.bak` so that the next save doesn't overwrite it.
+pub async fn set_aside(path: &Path, error: &Error) {
+    let backup_path = with_suffix(path, ".bak");
+    match tokio::fs::rename(path, &backup_path).await {
+        Ok(()) => eprintln!("{error:#}, moved it to {}", backup_path.display()),
+        Err(e) => eprintln!("{error:#}, and failed to move it out of the way: {e}"),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}